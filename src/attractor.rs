@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * Point pulling the boids within its radius (repeller if the strength is negative)
+ * In a configuration file : `{ position = [x, y], strength = s, radius = r }`
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attractor {
+    pub position: [f64; 2],
+    pub strength: f64, // Pull at the center, fading linearly to 0 at the radius (negative : push)
+    pub radius: f64,   // Falloff radius
+}
+
+impl Attractor {
+    pub fn is_repeller(&self) -> bool {
+        self.strength < 0.0
+    }
+
+    // Check that the attractor can be simulated
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.position[0].is_finite() && self.position[1].is_finite()) {
+            return Err("the position of an attractor must be finite".to_string());
+        }
+        if !self.strength.is_finite() {
+            return Err(format!("the strength of an attractor must be a finite number (got {})", self.strength));
+        }
+        if !(self.radius.is_finite() && self.radius > 0.0) {
+            return Err(format!("the radius of an attractor must be strictly positive (got {})", self.radius));
+        }
+        Ok(())
+    }
+}
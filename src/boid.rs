@@ -0,0 +1,967 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::attractor::Attractor;
+use crate::grid::SpatialGrid;
+use crate::obstacle::{Hit, Obstacle};
+use crate::predator::Predator;
+use crate::simulation::{BoundaryMode, LeaderMode, NeighborMode, SeparationFalloff, SimParams, SteeringMode};
+use crate::species::Species;
+use crate::variation::Traits;
+use crate::{
+    BOID_SIZE, EDGE_DETECTION_DISTANCE, FEELER_ANGLE, FOLLOW_DISTANCE, STEERING_FACTOR, WANDER_DISTANCE, WANDER_JITTER, WANDER_RADIUS, WAYPOINT_RADIUS,
+    WEIGHT_LEAD,
+};
+
+/**
+ * Sums of the neighbors seen by a boid for each rule, weighted by the affinity
+ * of its species for theirs
+ */
+#[derive(Default)]
+struct Neighborhood {
+    com: [f64; 2],
+    steer_away: [f64; 2],
+    repulsion: [f64; 2],
+    alignement_vector: [f64; 2],
+    headings: [f64; 2], // Sum of the unit headings of the alignment neighbors, weighted
+    falloff: SeparationFalloff,
+    nb_cohesion: i32,
+    nb_separation: i32,
+    nb_alignment: i32,
+    weights: [f64; 3], // Sum of the weights of the neighbors of each rule
+}
+
+/**
+ * Everything a boid reacts to besides the other boids
+ */
+#[derive(Clone, Copy, Default)]
+pub struct Surroundings<'a> {
+    pub obstacles: &'a [Obstacle],
+    pub predators: &'a [Predator],
+    pub attractors: &'a [Attractor],
+    pub species: &'a [Species],
+    pub leaders: &'a [Boid],           // Copies of the leaders of the current frame
+    pub path: &'a [[f64; 2]],          // Closed path of the leaders in path mode
+    pub leader_target: Option<[f64; 2]>, // Point the leaders go to in manual mode
+}
+
+/**
+ * Buffer of the topological neighbor search, reused from one boid and one step to the next
+ * (squared distance, offset, index and rule weights of each candidate)
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Candidates(Vec<(f64, [f64; 2], usize, [f64; 3])>);
+
+impl Neighborhood {
+    fn new(falloff: SeparationFalloff) -> Neighborhood {
+        Neighborhood { falloff, ..Default::default() }
+    }
+
+    /**
+     * Count a neighbor in the rules it has a weight for (cohesion, separation, alignment)
+     * Positions are summed as offsets, so the averages stay next to the boid across the edges
+     */
+    fn add(&mut self, weights: [f64; 3], offset: [f64; 2], velocity: [f64; 2]) {
+        if weights[0] > 0.0 {
+            self.com[0] += offset[0] * weights[0];
+            self.com[1] += offset[1] * weights[0];
+            self.weights[0] += weights[0];
+            self.nb_cohesion += 1;
+        }
+        if weights[1] > 0.0 {
+            self.steer_away[0] += offset[0] * weights[1];
+            self.steer_away[1] += offset[1] * weights[1];
+            self.weights[1] += weights[1];
+            self.nb_separation += 1;
+            // push away from the neighbor, of strength 1 at a boid length
+            let distance = (offset[0].powi(2) + offset[1].powi(2)).sqrt();
+            if distance > 0.0 {
+                let strength = weights[1] * match self.falloff {
+                    SeparationFalloff::Average => 0.0,
+                    SeparationFalloff::Inverse => BOID_SIZE / distance,
+                    SeparationFalloff::InverseSquare => (BOID_SIZE / distance).powi(2),
+                };
+                self.repulsion[0] -= offset[0] / distance * strength;
+                self.repulsion[1] -= offset[1] / distance * strength;
+            }
+        }
+        if weights[2] > 0.0 {
+            self.alignement_vector[0] += velocity[0] * weights[2];
+            self.alignement_vector[1] += velocity[1] * weights[2];
+            let speed = (velocity[0].powi(2) + velocity[1].powi(2)).sqrt();
+            if speed > 0.0 {
+                self.headings[0] += velocity[0] / speed * weights[2];
+                self.headings[1] += velocity[1] / speed * weights[2];
+            }
+            self.weights[2] += weights[2];
+            self.nb_alignment += 1;
+        }
+    }
+
+    // Turn the sums into averages, and the offsets back into points around `position`
+    // (they may lie beyond an edge in a wrapping world)
+    fn finish(&mut self, position: [f64; 2]) {
+        average(&mut self.com, self.weights[0]);
+        average(&mut self.steer_away, self.weights[1]);
+        average(&mut self.alignement_vector, self.weights[2]);
+        for point in [&mut self.com, &mut self.steer_away] {
+            point[0] += position[0];
+            point[1] += position[1];
+        }
+    }
+}
+
+// Scale a vector down to `max` if it is longer
+fn limit(vector: &mut [f64; 2], max: f64) {
+    let norm = (vector[0].powi(2) + vector[1].powi(2)).sqrt();
+    if norm > max {
+        vector[0] = vector[0] * max / norm;
+        vector[1] = vector[1] * max / norm;
+    }
+}
+
+// Cosine of the half angle of the perception cone of each rule (no limit for a full circle)
+fn fov_limits(params: &SimParams) -> [f64; 3] {
+    params.fovs().map(|fov| if fov >= 360.0 { f64::NEG_INFINITY } else { (fov.to_radians() / 2.0).cos() })
+}
+
+// Divide a weighted sum of vectors by the sum of the weights (if any)
+fn average(vector: &mut [f64; 2], weight: f64) {
+    if weight > 0.0 {
+        vector[0] /= weight;
+        vector[1] /= weight;
+    }
+}
+
+// Affinity and alignment affinity of a boid of `species` for the boids of species `other` (1 without species)
+fn affinities(species: Option<&Species>, other: usize) -> [f64; 2] {
+    species.map_or([1.0, 1.0], |species| [species.affinity(other), species.alignment_affinity(other)])
+}
+
+/**
+ * Weight of a neighbor in each rule (cohesion, separation, alignment) for given affinities :
+ * cohesion follows the positive affinity and alignment the positive alignment affinity,
+ * separation keeps its full weight within the separation radius and pushes away
+ * the avoided species within the cohesion radius
+ */
+fn affinity_weights([affinity, alignment_affinity]: [f64; 2], in_cohesion: bool, in_separation: bool, in_alignment: bool) -> [f64; 3] {
+    let attraction = affinity.max(0.0);
+    let avoidance = (-affinity).max(0.0);
+    [
+        if in_cohesion { attraction } else { 0.0 },
+        if in_separation { avoidance.max(1.0) } else if in_cohesion { avoidance } else { 0.0 },
+        if in_alignment { alignment_affinity.max(0.0) } else { 0.0 },
+    ]
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Boid {
+    pub id: i32,
+    pub x: f64,
+    pub y: f64,
+    pub dx: f64,
+    pub dy: f64,
+    pub distance_from_edges: [f64; 4],
+    // angle: f64,
+    pub angle: f64,
+    pub velocity: [f64; 2],
+    pub separation: [f64; 2],
+    pub alignment: [f64; 2],
+    pub cohesion: [f64; 2],
+    pub com: [f64; 2],
+    pub steer_away: [f64; 2],
+    pub alignement_vector: [f64; 2],
+
+    //Number of neighbors seen at the last update (cohesion, separation, alignment)
+    #[serde(default)]
+    pub nb_neighbors: [i32; 3],
+    //Direction steering around the obstacles ahead (null if none)
+    #[serde(default)]
+    pub avoidance: [f64; 2],
+    //Direction away from the predators in sight (null if none)
+    #[serde(default)]
+    pub threat: [f64; 2],
+    #[serde(default)]
+    pub flee: [f64; 2],
+    //Sum of the pulls of the attractors (and pushes of the repellers) in range
+    #[serde(default)]
+    pub pull: [f64; 2],
+    #[serde(default)]
+    pub attraction: [f64; 2],
+    //Steering towards the goal (null without goal)
+    #[serde(default)]
+    pub seek: [f64; 2],
+    //Sum of the pushes away from the separation neighbors (distance-weighted separation)
+    #[serde(default)]
+    pub repulsion: [f64; 2],
+    //Index of the species of the boid
+    #[serde(default)]
+    pub species: usize,
+    //Individual multipliers of the parameters of its species
+    #[serde(default)]
+    pub traits: Traits,
+    //Leaders go along the path (or to the mouse) instead of flocking
+    #[serde(default)]
+    pub leader: bool,
+    //Index of the point of the path a leader goes to
+    #[serde(default)]
+    pub waypoint: usize,
+    //Offset to the point behind the closest leader in sight (null if none)
+    #[serde(default)]
+    pub lead: [f64; 2],
+    #[serde(default)]
+    pub follow: [f64; 2],
+    //Angle of the wander target on its circle, relative to the heading
+    #[serde(default)]
+    pub wander_angle: f64,
+    //Offset to the wander target (null if the rule is off)
+    #[serde(default)]
+    pub wander_target: [f64; 2],
+    #[serde(default)]
+    pub wander: [f64; 2],
+    //Weighted sum of the unit headings of the alignment neighbors (Vicsek model)
+    #[serde(default)]
+    pub headings: [f64; 2],
+}
+
+impl Boid {
+    // Create a boid heading towards `angle` at `speed` pixels per frame
+    pub fn new(i: i32, x: f64, y: f64, angle: f64, speed: f64) -> Boid {
+        Boid {
+            id: i,
+            x,
+            y,
+            dx: 0.0,
+            dy: 0.0,
+            angle,
+            // velocity: [1.0, 1.0],
+            distance_from_edges: [0.0, 0.0, 0.0, 0.0],
+            velocity: [angle.cos() * speed, angle.sin() * speed],
+            separation: [0.0, 0.0],
+            alignment: [0.0, 0.0],
+            cohesion: [0.0, 0.0],
+            //Center Of Mass
+            com: [0.0, 0.0],
+            //Point to steer away from
+            steer_away: [0.0, 0.0],
+            //Vector to align with
+            alignement_vector: [0.0, 0.0],
+
+            nb_neighbors: [0, 0, 0],
+            avoidance: [0.0, 0.0],
+            threat: [0.0, 0.0],
+            flee: [0.0, 0.0],
+            pull: [0.0, 0.0],
+            attraction: [0.0, 0.0],
+            seek: [0.0, 0.0],
+            repulsion: [0.0, 0.0],
+            species: 0,
+            traits: Traits::default(),
+            leader: false,
+            waypoint: 0,
+            lead: [0.0, 0.0],
+            follow: [0.0, 0.0],
+            wander_angle: 0.0,
+            wander_target: [0.0, 0.0],
+            wander: [0.0, 0.0],
+            headings: [0.0, 0.0],
+        }
+    }
+
+    /**
+     * Update the boid's position for a step of `dt` seconds
+     * The velocity is expressed in pixels per frame (1 / params.frame_rate seconds)
+     * `rng` drives the wander rule and the noise (untouched when both are off),
+     * `candidates` is the buffer of the topological neighbor search
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn update<R: Rng>(&mut self, dt: f64, boids: &[Boid], grid: &SpatialGrid, params: &SimParams, surroundings: &Surroundings, candidates: &mut Candidates, rng: &mut R) {
+        // speed, weights and radii of the species of the boid, scaled by its own traits
+        let species = surroundings.species.get(self.species);
+        let params = &self.traits.apply(&species.map_or(*params, |species| species.apply(params)));
+        // a leader seeks its own target as a goal
+        let params = &if self.leader {
+            SimParams { goal: self.leader_target(params, surroundings), weight_goal: WEIGHT_LEAD, ..*params }
+        } else {
+            *params
+        };
+        self.update_distance_from_edges(params);
+
+        // self.com  = self.get_center_of_mass(boids);
+        // self.steer_away = self.get_steer_away(boids);
+
+        // single neighbor pass feeding the three rules
+        let neighborhood = match params.neighbor_mode {
+            NeighborMode::Metric => self.gather_neighbors(boids, grid, params, species),
+            NeighborMode::Topological => self.gather_nearest(boids, grid, params, species, candidates),
+        };
+        self.com = neighborhood.com;
+        self.steer_away = neighborhood.steer_away;
+        self.repulsion = neighborhood.repulsion;
+        self.alignement_vector = neighborhood.alignement_vector;
+        self.headings = neighborhood.headings;
+
+        self.nb_neighbors = [neighborhood.nb_cohesion, neighborhood.nb_separation, neighborhood.nb_alignment];
+        // leaders only keep their distance from the flock
+        if self.leader {
+            self.nb_neighbors[0] = 0;
+            self.nb_neighbors[2] = 0;
+        }
+        self.lead = if self.leader { [0.0, 0.0] } else { self.sense_leaders(params, surroundings.leaders) };
+        self.avoidance = self.avoid_obstacles(params, surroundings.obstacles);
+        self.threat = self.sense_predators(params, surroundings.predators);
+        self.pull = self.sense_attractors(params, surroundings.attractors);
+
+        let frames = dt * params.frame_rate as f64;
+        self.wander_target = if params.weight_wander != 0.0 { self.update_wander(rng, frames) } else { [0.0, 0.0] };
+        match params.steering {
+            SteeringMode::Force => self.apply_steering_forces(params, frames),
+            SteeringMode::TurnRate => self.apply_turn_rate(params, dt, frames),
+            SteeringMode::Vicsek => self.apply_vicsek(params),
+        }
+        // the Vicsek noise is applied once per step, whatever its length
+        if params.noise > 0.0 {
+            let strength = if params.steering == SteeringMode::Vicsek { params.noise } else { params.noise * frames };
+            self.add_noise(rng, strength);
+        }
+
+        // update boid's position
+        self.x += self.velocity[0] * frames;
+        self.y += self.velocity[1] * frames;
+        match params.boundary {
+            BoundaryMode::Wrap => self.wrap_around(params),
+            BoundaryMode::Bounce => self.bounce(params),
+            BoundaryMode::Steer | BoundaryMode::Open => {},
+        }
+        //update the boid's angle
+        self.angle = self.velocity[1].atan2(self.velocity[0]);
+    }
+
+    /**
+     * Steering-force model : each rule gives a steering force towards its desired
+     * velocity, the weighted sum is the acceleration (limited to the maximum force)
+     */
+    fn apply_steering_forces(&mut self, params: &SimParams, frames: f64) {
+        let [nb_neighbors_cohesion, nb_neighbors_separation, nb_neighbors_alignement] = self.nb_neighbors;
+        if nb_neighbors_cohesion > 0 {
+            self.update_cohesion(params);
+        } else {
+            self.cohesion = [0.0, 0.0];
+        }
+
+        if nb_neighbors_separation > 0 {
+            self.update_separation(params);
+        } else {
+            self.separation = [0.0, 0.0];
+        }
+
+        if nb_neighbors_alignement > 0 {
+            self.update_alignment(params);
+        } else {
+            self.alignment = [0.0, 0.0];
+        }
+
+        self.flee = self.steer_towards(self.threat, params);
+        self.follow = self.steer_towards(self.lead, params);
+        self.wander = self.steer_towards(self.wander_target, params);
+
+        // the attractors steer as hard as their summed strength
+        let pull = (self.pull[0].powi(2) + self.pull[1].powi(2)).sqrt();
+        let attraction = self.steer_towards(self.pull, params);
+        self.attraction = [attraction[0] * pull, attraction[1] * pull];
+
+        self.seek = match params.goal {
+            Some(goal) => self.steer_towards(params.offset([self.x, self.y], goal), params),
+            None => [0.0, 0.0],
+        };
+
+        // weighted sum of the steering forces, limited to the maximum force
+        let mut acceleration = [0.0, 0.0];
+        for (i, component) in acceleration.iter_mut().enumerate() {
+            *component = self.cohesion[i] * params.weight_cohesion
+                + self.separation[i] * params.weight_separation
+                + self.alignment[i] * params.weight_alignment
+                + self.flee[i] * params.weight_flee
+                + self.follow[i] * params.weight_follow
+                + self.wander[i] * params.weight_wander
+                + self.attraction[i]
+                + self.seek[i] * params.weight_goal;
+        }
+        limit(&mut acceleration, params.max_force);
+
+        // soft walls push harder than the rules
+        if params.boundary == BoundaryMode::Steer {
+            let push = self.avoid_edges();
+            acceleration[0] += push[0] * STEERING_FACTOR * params.max_force;
+            acceleration[1] += push[1] * STEERING_FACTOR * params.max_force;
+        }
+        // and so do the obstacles
+        acceleration[0] += self.avoidance[0] * params.weight_avoidance * params.max_force;
+        acceleration[1] += self.avoidance[1] * params.weight_avoidance * params.max_force;
+
+        // integrate the acceleration (scaled to the length of the step in frames)
+        self.velocity[0] += acceleration[0] * frames;
+        self.velocity[1] += acceleration[1] * frames;
+
+        self.enforce_max_speed(params.max_speed);
+    }
+
+    /**
+     * Turn-rate model : each rule turns the heading towards its own target vector,
+     * the weighted turn is bounded by the maximum turn rate, so the boids bank smoothly.
+     * The speed goes towards the maximum speed, limited by the maximum force.
+     */
+    fn apply_turn_rate(&mut self, params: &SimParams, dt: f64, frames: f64) {
+        let [nb_neighbors_cohesion, nb_neighbors_separation, nb_neighbors_alignement] = self.nb_neighbors;
+        // target vector of each rule (zero without neighbors)
+        self.cohesion = if nb_neighbors_cohesion > 0 { [self.com[0] - self.x, self.com[1] - self.y] } else { [0.0, 0.0] };
+        self.separation = if nb_neighbors_separation > 0 { self.separation_target(params) } else { [0.0, 0.0] };
+        self.alignment = if nb_neighbors_alignement > 0 { self.alignement_vector } else { [0.0, 0.0] };
+        self.flee = self.threat;
+        self.follow = self.lead;
+        self.wander = self.wander_target;
+        self.attraction = self.pull;
+        self.seek = match params.goal {
+            Some(goal) => params.offset([self.x, self.y], goal),
+            None => [0.0, 0.0],
+        };
+
+        let pull = (self.pull[0].powi(2) + self.pull[1].powi(2)).sqrt();
+        let mut omega = self.smooth_angle(self.cohesion) * params.weight_cohesion
+            + self.smooth_angle(self.separation) * params.weight_separation * self.separation_strength(params)
+            + self.smooth_angle(self.alignment) * params.weight_alignment
+            + self.smooth_angle(self.flee) * params.weight_flee
+            + self.smooth_angle(self.follow) * params.weight_follow
+            + self.smooth_angle(self.wander) * params.weight_wander
+            + self.smooth_angle(self.attraction) * pull
+            + self.smooth_angle(self.seek) * params.weight_goal;
+        // soft walls turn the boid away, weighted by how close the edges are
+        if params.boundary == BoundaryMode::Steer {
+            let push = self.avoid_edges();
+            let strength = (push[0].powi(2) + push[1].powi(2)).sqrt();
+            omega += self.smooth_angle(push) * strength * STEERING_FACTOR;
+        }
+        // as do the obstacles ahead
+        let strength = (self.avoidance[0].powi(2) + self.avoidance[1].powi(2)).sqrt();
+        omega += self.smooth_angle(self.avoidance) * strength * params.weight_avoidance;
+        let max_turn = params.max_turn_rate * dt;
+        let omega = omega.clamp(-max_turn, max_turn);
+
+        let speed = (self.velocity[0].powi(2) + self.velocity[1].powi(2)).sqrt();
+        let speed = (speed + params.max_force * frames).min(params.max_speed);
+        let heading = self.velocity[1].atan2(self.velocity[0]) + omega;
+        self.velocity = [heading.cos() * speed, heading.sin() * speed];
+    }
+
+    /**
+     * Signed angle (radians) to turn the velocity towards `target`
+     * (0 if the boid is stopped or the target is null)
+     */
+    fn smooth_angle(&self, target: [f64; 2]) -> f64 {
+        let cross = self.velocity[0] * target[1] - self.velocity[1] * target[0];
+        let dot = self.velocity[0] * target[0] + self.velocity[1] * target[1];
+        if cross == 0.0 && dot == 0.0 {
+            0.0
+        } else {
+            cross.atan2(dot)
+        }
+    }
+
+    /**
+     * Average position (cohesion, separation) and velocity (alignment) of the
+     * neighbors inside each rule radius and perception cone, found through the spatial grid
+     * (distances and averages are measured across the edges in a wrapping world)
+     */
+    fn gather_neighbors(&self, boids: &[Boid], grid: &SpatialGrid, params: &SimParams, species: Option<&Species>) -> Neighborhood {
+        let mut neighborhood = Neighborhood::new(params.separation_falloff);
+        let [radius_cohesion, radius_separation, radius_alignment] = [params.radius_cohesion, params.radius_separation, params.radius_alignment];
+        let search_radius = params.max_radius();
+        let [cos_cohesion, cos_separation, cos_alignment] = fov_limits(params);
+        let speed = (self.velocity[0].powi(2) + self.velocity[1].powi(2)).sqrt();
+        grid.query(self.x, self.y, search_radius, |i| {
+            let boid = &boids[i];
+            if boid.id == self.id {
+                return;
+            }
+            let offset = params.offset([self.x, self.y], [boid.x, boid.y]);
+            let distance = offset[0].powi(2) + offset[1].powi(2);
+            let cos_angle = self.cos_angle(speed, offset, distance);
+            let weights = affinity_weights(
+                affinities(species, boid.species),
+                distance < radius_cohesion.powi(2) && cos_angle >= cos_cohesion,
+                distance < radius_separation.powi(2) && cos_angle >= cos_separation,
+                distance < radius_alignment.powi(2) && cos_angle >= cos_alignment,
+            );
+            neighborhood.add(weights, offset, boid.velocity);
+        });
+        neighborhood.finish([self.x, self.y]);
+        neighborhood
+    }
+
+    /**
+     * Same averages over the k nearest neighbors of each rule inside its perception cone
+     * (topological mode), among the boids the affinity gives a weight in that rule.
+     * The search radius doubles until every rule has its k neighbors, or covers the whole world.
+     */
+    fn gather_nearest(&self, boids: &[Boid], grid: &SpatialGrid, params: &SimParams, species: Option<&Species>, candidates: &mut Candidates) -> Neighborhood {
+        let ks = [params.k_cohesion, params.k_separation, params.k_alignment];
+        let limits = fov_limits(params);
+        let speed = (self.velocity[0].powi(2) + self.velocity[1].powi(2)).sqrt();
+        let world_size = params.width + params.height;
+
+        // boids within the search radius
+        let candidates = &mut candidates.0;
+        let mut radius = grid.cell_size();
+        loop {
+            candidates.clear();
+            grid.query(self.x, self.y, radius, |i| {
+                let boid = &boids[i];
+                if boid.id == self.id {
+                    return;
+                }
+                let offset = params.offset([self.x, self.y], [boid.x, boid.y]);
+                let distance = offset[0].powi(2) + offset[1].powi(2);
+                // only the boids within the radius are sure to have all been visited
+                if distance < radius.powi(2) {
+                    candidates.push((distance, offset, i, affinity_weights(affinities(species, boid.species), true, true, true)));
+                }
+            });
+            let enough = (0..3).all(|rule| {
+                candidates.iter().filter(|(distance, offset, _, weights)| weights[rule] > 0.0 && self.cos_angle(speed, *offset, *distance) >= limits[rule]).count() >= ks[rule]
+            });
+            if enough || radius > world_size {
+                break;
+            }
+            radius *= 2.0;
+        }
+
+        // unstable sort : no buffer allocated (ties are broken by index to stay deterministic)
+        candidates.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then(a.2.cmp(&b.2)));
+        let mut neighborhood = Neighborhood::new(params.separation_falloff);
+        let mut counts = [0; 3];
+        for &(distance, offset, i, weights) in candidates.iter() {
+            let cos_angle = self.cos_angle(speed, offset, distance);
+            let weights: [f64; 3] = std::array::from_fn(|rule| {
+                if weights[rule] > 0.0 && counts[rule] < ks[rule] && cos_angle >= limits[rule] {
+                    counts[rule] += 1;
+                    weights[rule]
+                } else {
+                    0.0
+                }
+            });
+            neighborhood.add(weights, offset, boids[i].velocity);
+        }
+        neighborhood.finish([self.x, self.y]);
+        neighborhood
+    }
+
+    /**
+     * Cosine of the angle between the heading and the direction of a neighbor
+     * (1 for a stopped boid, which sees all around). `distance` is squared.
+     */
+    fn cos_angle(&self, speed: f64, offset: [f64; 2], distance: f64) -> f64 {
+        if speed > 0.0 && distance > 0.0 {
+            (self.velocity[0] * offset[0] + self.velocity[1] * offset[1]) / (speed * distance.sqrt())
+        } else {
+            1.0
+        }
+    }
+
+    /**
+     * Direction away from the predators closer than the flee radius,
+     * each one weighted from 0 at the radius to 1 when it is on the boid
+     */
+    fn sense_predators(&self, params: &SimParams, predators: &[Predator]) -> [f64; 2] {
+        let mut away = [0.0, 0.0];
+        for predator in predators {
+            let offset = params.offset([predator.x, predator.y], [self.x, self.y]);
+            let distance = (offset[0].powi(2) + offset[1].powi(2)).sqrt();
+            if distance < params.radius_flee && distance > 0.0 {
+                let strength = 1.0 - distance / params.radius_flee;
+                away[0] += offset[0] / distance * strength;
+                away[1] += offset[1] / distance * strength;
+            }
+        }
+        away
+    }
+
+    /**
+     * Offset to the point `FOLLOW_DISTANCE` behind the closest leader within the follow radius
+     */
+    fn sense_leaders(&self, params: &SimParams, leaders: &[Boid]) -> [f64; 2] {
+        let mut closest: Option<(f64, [f64; 2], &Boid)> = None;
+        for leader in leaders {
+            let offset = params.offset([self.x, self.y], [leader.x, leader.y]);
+            let distance = offset[0].powi(2) + offset[1].powi(2);
+            if distance < params.radius_follow.powi(2) && closest.is_none_or(|(closest_distance, _, _)| distance < closest_distance) {
+                closest = Some((distance, offset, leader));
+            }
+        }
+        let Some((_, offset, leader)) = closest else {
+            return [0.0, 0.0];
+        };
+        let speed = (leader.velocity[0].powi(2) + leader.velocity[1].powi(2)).sqrt();
+        if speed > 0.0 {
+            [offset[0] - leader.velocity[0] / speed * FOLLOW_DISTANCE, offset[1] - leader.velocity[1] / speed * FOLLOW_DISTANCE]
+        } else {
+            offset
+        }
+    }
+
+    /**
+     * Target of a leader : the next point of the path (moving on within `WAYPOINT_RADIUS`),
+     * or the point set by the user in manual mode
+     */
+    fn leader_target(&mut self, params: &SimParams, surroundings: &Surroundings) -> Option<[f64; 2]> {
+        match params.leader_mode {
+            LeaderMode::Manual => surroundings.leader_target,
+            LeaderMode::Path if surroundings.path.is_empty() => None,
+            LeaderMode::Path => {
+                let path = surroundings.path;
+                let offset = params.offset([self.x, self.y], path[self.waypoint % path.len()]);
+                if offset[0].powi(2) + offset[1].powi(2) < WAYPOINT_RADIUS.powi(2) {
+                    self.waypoint += 1;
+                }
+                self.waypoint %= path.len();
+                Some(path[self.waypoint])
+            },
+        }
+    }
+
+    /**
+     * Sum of the pulls of the attractors within their radius (towards them,
+     * away from the repellers), each one fading from its strength at the center to 0 at its radius
+     */
+    fn sense_attractors(&self, params: &SimParams, attractors: &[Attractor]) -> [f64; 2] {
+        let mut pull = [0.0, 0.0];
+        for attractor in attractors {
+            let offset = params.offset([self.x, self.y], attractor.position);
+            let distance = (offset[0].powi(2) + offset[1].powi(2)).sqrt();
+            if distance < attractor.radius && distance > 0.0 {
+                let strength = attractor.strength * (1.0 - distance / attractor.radius);
+                pull[0] += offset[0] / distance * strength;
+                pull[1] += offset[1] / distance * strength;
+            }
+        }
+        pull
+    }
+
+    /**
+     * Steering force turning the velocity towards `direction` at full speed
+     * (Reynolds : steering = desired velocity - velocity, limited to the maximum force)
+     */
+    fn steer_towards(&self, direction: [f64; 2], params: &SimParams) -> [f64; 2] {
+        let norm = (direction[0].powi(2) + direction[1].powi(2)).sqrt();
+        if norm == 0.0 {
+            return [0.0, 0.0];
+        }
+        let mut steering = [
+            direction[0] / norm * params.max_speed - self.velocity[0],
+            direction[1] / norm * params.max_speed - self.velocity[1],
+        ];
+        limit(&mut steering, params.max_force);
+        steering
+    }
+
+    /**
+     * Change the cohesion vector to steer towards the center of mass of the flock
+     */
+    fn update_cohesion(&mut self, params: &SimParams) {
+        self.cohesion = self.steer_towards([self.com[0] - self.x, self.com[1] - self.y], params);
+    }
+
+    /**
+     * Change the separation vector to steer away from the close neighbors
+     * (harder the closer they are with a distance-weighted falloff)
+     */
+    fn update_separation(&mut self, params: &SimParams) {
+        let separation = self.steer_towards(self.separation_target(params), params);
+        let strength = self.separation_strength(params);
+        self.separation = [separation[0] * strength, separation[1] * strength];
+    }
+
+    // Direction away from the close neighbors
+    fn separation_target(&self, params: &SimParams) -> [f64; 2] {
+        match params.separation_falloff {
+            SeparationFalloff::Average => [self.x - self.steer_away[0], self.y - self.steer_away[1]],
+            SeparationFalloff::Inverse | SeparationFalloff::InverseSquare => self.repulsion,
+        }
+    }
+
+    // Scale of the separation rule : 1 for the average, the summed pushes otherwise
+    fn separation_strength(&self, params: &SimParams) -> f64 {
+        match params.separation_falloff {
+            SeparationFalloff::Average => 1.0,
+            SeparationFalloff::Inverse | SeparationFalloff::InverseSquare => (self.repulsion[0].powi(2) + self.repulsion[1].powi(2)).sqrt(),
+        }
+    }
+
+    /**
+     * Change the alignment vector to steer towards the mean velocity of the neighbors
+     */
+    fn update_alignment(&mut self, params: &SimParams) {
+        self.alignment = self.steer_towards(self.alignement_vector, params);
+    }
+
+    /**
+     * Ensure that the boid's speed does not exceed the maximum speed
+     */
+    fn enforce_max_speed(&mut self, max_speed: f64) {
+        limit(&mut self.velocity, max_speed);
+    }
+
+    /**
+     * Direction steering the boid around the obstacles, found with three feelers
+     * (one straight ahead, two half as long at +/- `FEELER_ANGLE`)
+     * The closest hit gives a sideways push, scaled from 0 at the tip of the feeler
+     * to 1 on the surface. A boid inside an obstacle is pushed out of it.
+     */
+    fn avoid_obstacles(&self, params: &SimParams, obstacles: &[Obstacle]) -> [f64; 2] {
+        let speed = (self.velocity[0].powi(2) + self.velocity[1].powi(2)).sqrt();
+        if obstacles.is_empty() || speed == 0.0 {
+            return [0.0, 0.0];
+        }
+        let position = [self.x, self.y];
+        if let Some(obstacle) = obstacles.iter().find(|obstacle| obstacle.contains(position)) {
+            let center = obstacle.center();
+            let away = [self.x - center[0], self.y - center[1]];
+            let norm = (away[0].powi(2) + away[1].powi(2)).sqrt();
+            return if norm > 0.0 { [away[0] / norm, away[1] / norm] } else { [1.0, 0.0] };
+        }
+
+        let heading = [self.velocity[0] / speed, self.velocity[1] / speed];
+        let lookahead = params.obstacle_lookahead;
+        let mut closest: Option<(f64, Hit)> = None;
+        for (angle, length) in [(0.0, lookahead), (FEELER_ANGLE, lookahead / 2.0), (-FEELER_ANGLE, lookahead / 2.0)] {
+            let direction = [
+                heading[0] * angle.cos() - heading[1] * angle.sin(),
+                heading[0] * angle.sin() + heading[1] * angle.cos(),
+            ];
+            for obstacle in obstacles {
+                if let Some(hit) = obstacle.ray_hit(position, direction, length) {
+                    let strength = 1.0 - hit.distance / length;
+                    if closest.is_none_or(|(closest_strength, _)| strength > closest_strength) {
+                        closest = Some((strength, hit));
+                    }
+                }
+            }
+        }
+
+        match closest {
+            Some((strength, hit)) => {
+                // steer along the part of the surface normal across the heading
+                let along = hit.normal[0] * heading[0] + hit.normal[1] * heading[1];
+                let lateral = [hit.normal[0] - along * heading[0], hit.normal[1] - along * heading[1]];
+                let norm = (lateral[0].powi(2) + lateral[1].powi(2)).sqrt();
+                // head-on : turn left
+                let lateral = if norm > 1e-9 { [lateral[0] / norm, lateral[1] / norm] } else { [-heading[1], heading[0]] };
+                [lateral[0] * strength, lateral[1] * strength]
+            },
+            None => [0.0, 0.0],
+        }
+    }
+
+    fn update_distance_from_edges(&mut self, params: &SimParams) {
+        //calculate the distance from the edges of the screen
+        // 0 : top
+        // 1 : right
+        // 2 : bottom
+        // 3 : left
+        // (negative once the boid is outside of the world)
+        self.distance_from_edges[0] = self.y;
+        self.distance_from_edges[1] = params.width - self.x;
+        self.distance_from_edges[2] = params.height - self.y;
+        self.distance_from_edges[3] = self.x;
+    }
+
+    // Teleport the boid to the opposite edge when it leaves the world
+    fn wrap_around(&mut self, params: &SimParams) {
+        self.x = self.x.rem_euclid(params.width);
+        self.y = self.y.rem_euclid(params.height);
+    }
+
+    // Reflect the boid on the edges it crossed
+    fn bounce(&mut self, params: &SimParams) {
+        if self.x < 0.0 {
+            self.x = -self.x;
+            self.velocity[0] = self.velocity[0].abs();
+        } else if self.x > params.width {
+            self.x = 2.0 * params.width - self.x;
+            self.velocity[0] = -self.velocity[0].abs();
+        }
+
+        if self.y < 0.0 {
+            self.y = -self.y;
+            self.velocity[1] = self.velocity[1].abs();
+        } else if self.y > params.height {
+            self.y = 2.0 * params.height - self.y;
+            self.velocity[1] = -self.velocity[1].abs();
+        }
+
+        // a step longer than the world cannot be reflected once
+        self.x = self.x.clamp(0.0, params.width);
+        self.y = self.y.clamp(0.0, params.height);
+    }
+
+    /**
+     * Direction pushing the boid away from the edges closer than `EDGE_DETECTION_DISTANCE`
+     * Each edge adds its inward normal, scaled from 0 at the detection distance to 1 on the edge
+     * (and more outside of the world)
+     */
+    fn avoid_edges(&self) -> [f64; 2] {
+        let mut push = [0.0, 0.0];
+        for (i, distance) in self.distance_from_edges.iter().enumerate() {
+            if *distance < EDGE_DETECTION_DISTANCE {
+                let n: [f64; 2] = match i {
+                    0 => [0., 1.],
+                    1 => [-1., 0.],
+                    2 => [0., -1.],
+                    _ => [1., 0.],
+                };
+                let strength = 1. - distance / EDGE_DETECTION_DISTANCE;
+                push[0] += n[0] * strength;
+                push[1] += n[1] * strength;
+            }
+        }
+        push
+    }
+
+    /**
+     * Vicsek model : the boid takes the mean heading of itself and its alignment
+     * neighbors at the maximum speed (the noise is added afterwards), every other rule is ignored
+     * but the edge push of the steer boundary
+     */
+    fn apply_vicsek(&mut self, params: &SimParams) {
+        let mut heading = self.velocity[1].atan2(self.velocity[0]);
+        let speed = (self.velocity[0].powi(2) + self.velocity[1].powi(2)).sqrt();
+        // sum of the unit headings, whatever the speeds of the neighbors
+        let mut sum = if speed > 0.0 { [self.velocity[0] / speed, self.velocity[1] / speed] } else { [heading.cos(), heading.sin()] };
+        if self.nb_neighbors[2] > 0 {
+            sum[0] += self.headings[0];
+            sum[1] += self.headings[1];
+        }
+        if sum != [0.0, 0.0] {
+            heading = sum[1].atan2(sum[0]);
+        }
+        // the edge push turns the mean heading back inside the world
+        if params.boundary == BoundaryMode::Steer {
+            let push = self.avoid_edges();
+            let [x, y] = [heading.cos() + push[0] * STEERING_FACTOR, heading.sin() + push[1] * STEERING_FACTOR];
+            if [x, y] != [0.0, 0.0] {
+                heading = y.atan2(x);
+            }
+        }
+        self.velocity = [heading.cos() * params.max_speed, heading.sin() * params.max_speed];
+    }
+
+    /**
+     * Reynolds wander : a target on a circle `WANDER_DISTANCE` ahead of the boid,
+     * moved along the circle by a random jitter at each step. Returns its offset.
+     * A stopped boid keeps its last angle as heading.
+     */
+    fn update_wander<R: Rng>(&mut self, rng: &mut R, frames: f64) -> [f64; 2] {
+        self.wander_angle += rng.gen_range(-1.0..=1.0) * WANDER_JITTER * frames;
+        let heading = self.angle;
+        let around = heading + self.wander_angle;
+        [
+            heading.cos() * WANDER_DISTANCE + around.cos() * WANDER_RADIUS,
+            heading.sin() * WANDER_DISTANCE + around.sin() * WANDER_RADIUS,
+        ]
+    }
+
+    /**
+     * Vicsek angular noise : turn the velocity by a uniform random angle in
+     * [-strength / 2, strength / 2] (the speed is kept)
+     */
+    fn add_noise<R: Rng>(&mut self, rng: &mut R, strength: f64) {
+        let turn = rng.gen_range(-0.5..=0.5) * strength;
+        let (sin, cos) = turn.sin_cos();
+        self.velocity = [self.velocity[0] * cos - self.velocity[1] * sin, self.velocity[0] * sin + self.velocity[1] * cos];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn assert_close(a: [f64; 2], b: [f64; 2]) {
+        assert!((a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    // Average of vectors (zero without any)
+    fn mean(vectors: impl Iterator<Item = [f64; 2]>) -> [f64; 2] {
+        let mut sum = [0.0, 0.0];
+        let mut count = 0.0;
+        for vector in vectors {
+            sum[0] += vector[0];
+            sum[1] += vector[1];
+            count += 1.0;
+        }
+        average(&mut sum, count);
+        sum
+    }
+
+    // k nearest neighbors of each rule found by scanning the whole flock, checked against the grid search
+    fn check_nearest(params: &SimParams) {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let boids: Vec<Boid> = (0..40).map(|i| Boid::new(i, rng.gen_range(0.0..params.width), rng.gen_range(0.0..params.height), rng.gen_range(0.0..6.3), 2.0)).collect();
+        let mut grid = SpatialGrid::new();
+        grid.rebuild(&boids, params.width, params.height, params.max_radius(), params.boundary == BoundaryMode::Wrap);
+        let mut candidates = Candidates::default(); // shared by every boid, as in a step
+        let ks = [params.k_cohesion, params.k_separation, params.k_alignment];
+        let limits = fov_limits(params);
+
+        for boid in &boids {
+            let neighborhood = boid.gather_nearest(&boids, &grid, params, None, &mut candidates);
+            let speed = (boid.velocity[0].powi(2) + boid.velocity[1].powi(2)).sqrt();
+            let mut others: Vec<(f64, usize, [f64; 2])> = boids.iter().enumerate().filter(|(_, other)| other.id != boid.id).map(|(i, other)| {
+                let offset = params.offset([boid.x, boid.y], [other.x, other.y]);
+                (offset[0].powi(2) + offset[1].powi(2), i, offset)
+            }).collect();
+            others.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+            let nearest: Vec<Vec<&(f64, usize, [f64; 2])>> = (0..3).map(|rule| {
+                others.iter().filter(|(distance, _, offset)| boid.cos_angle(speed, *offset, *distance) >= limits[rule]).take(ks[rule]).collect()
+            }).collect();
+            assert_eq!([neighborhood.nb_cohesion, neighborhood.nb_separation, neighborhood.nb_alignment], std::array::from_fn(|rule| nearest[rule].len() as i32));
+
+            let com = mean(nearest[0].iter().map(|(_, _, offset)| *offset));
+            assert_close(neighborhood.com, [boid.x + com[0], boid.y + com[1]]);
+            assert_close(neighborhood.alignement_vector, mean(nearest[2].iter().map(|(_, i, _)| boids[*i].velocity)));
+        }
+    }
+
+    #[test]
+    fn affinities_weigh_each_rule() {
+        // flocks, keeps its distance, avoids
+        assert_eq!(affinity_weights([1.0, 1.0], true, true, true), [1.0, 1.0, 1.0]);
+        assert_eq!(affinity_weights([0.0, 0.0], true, true, true), [0.0, 1.0, 0.0]);
+        assert_eq!(affinity_weights([-1.0, 0.0], true, false, true), [0.0, 1.0, 0.0]);
+        // aligns without cohesion, or gathers without aligning
+        assert_eq!(affinity_weights([0.0, 1.0], true, false, true), [0.0, 0.0, 1.0]);
+        assert_eq!(affinity_weights([1.0, -1.0], true, false, true), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn gather_nearest_matches_a_full_scan() {
+        for boundary in [BoundaryMode::Wrap, BoundaryMode::Bounce] {
+            for (ks, fov) in [([3, 5, 7], 360.0), ([4, 2, 6], 120.0), ([100, 1, 39], 250.0)] {
+                let params = SimParams {
+                    width: 600.0,
+                    height: 400.0,
+                    boundary,
+                    neighbor_mode: NeighborMode::Topological,
+                    k_cohesion: ks[0],
+                    k_separation: ks[1],
+                    k_alignment: ks[2],
+                    fov,
+                    fov_separation: Some(360.0),
+                    ..SimParams::default()
+                };
+                check_nearest(&params);
+            }
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use std::path::PathBuf;
+
+use boids::{
+    BoundaryMode, Config, ConfigError, LeaderMode, NeighborMode, PredatorTarget, SeparationFalloff, SimParams, SteeringMode, ALIGNMENT_RADIUS, CAPTURE_RADIUS,
+    FIELD_OF_VIEW, FLEE_RADIUS, FLOCK_SIZE, FOLLOW_RADIUS, FRAME_RATE, HEIGHT, K_ALIGNMENT, K_COHESION, K_SEPARATION, MAX_BOID_SPEED, MAX_FORCE, MAX_OMEGA,
+    NB_BOIDS, NB_LEADERS, NB_PREDATORS, NOISE, OBSTACLE_LOOKAHEAD, PREDATOR_SPEED, PREDATOR_TURN_RATE, SEPARATION_RADIUS, WEIGHT_ALIGNMENT, WEIGHT_AVOIDANCE,
+    WEIGHT_COHESION, WEIGHT_FLEE, WEIGHT_FOLLOW, WEIGHT_GOAL, WEIGHT_SEPARATION, WEIGHT_WANDER, WIDTH,
+};
+
+/// Boids flocking simulation
+#[derive(Parser)]
+#[command(allow_negative_numbers = true)]
+pub struct Cli {
+    /// Configuration file (TOML, or JSON with a `.json` extension)
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Built-in configuration preset
+    #[arg(long, conflicts_with = "config", value_parser = clap::builder::PossibleValuesParser::new(Config::preset_names()))]
+    pub preset: Option<String>,
+
+    /// Seed of the simulation RNG (random if not given)
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// File written by the save key and read by the load key
+    #[arg(long, value_name = "FILE", default_value = "snapshot.json")]
+    pub snapshot: PathBuf,
+
+    /// Start from a snapshot instead of spawning new boids
+    #[arg(long, value_name = "FILE")]
+    pub load_snapshot: Option<PathBuf>,
+
+    /// Record the trajectories of the run to a file
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Play back a recorded trajectory file instead of simulating
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["record", "load_snapshot"])]
+    pub replay: Option<PathBuf>,
+
+    /// Run without a window and export the trajectories to CSV (needs --steps and --out)
+    #[arg(long, requires_all = ["steps", "out"], conflicts_with = "replay")]
+    pub headless: bool,
+
+    /// Number of steps to simulate in headless mode
+    #[arg(long, requires = "headless")]
+    pub steps: Option<u64>,
+
+    /// CSV file written in headless mode
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    pub out: Option<PathBuf>,
+
+    /// CSV file receiving the flock metrics of each step in headless mode
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    pub metrics_out: Option<PathBuf>,
+
+    /// Number of boids to generate
+    #[arg(long, default_value_t = NB_BOIDS)]
+    pub boids: usize,
+
+    /// Number of species the boids are spread over (at least as many as described in the configuration)
+    #[arg(long, default_value_t = 1)]
+    pub species: usize,
+
+    /// Width of the world (and window) in pixels
+    #[arg(long, default_value_t = WIDTH, value_parser = positive)]
+    pub width: f64,
+
+    /// Height of the world (and window) in pixels
+    #[arg(long, default_value_t = HEIGHT, value_parser = positive)]
+    pub height: f64,
+
+    /// Simulation updates per second
+    #[arg(long, default_value_t = FRAME_RATE, value_parser = clap::value_parser!(u64).range(1..))]
+    pub fps: u64,
+
+    /// Behavior at the edges of the world: wrap (torus), bounce, steer (soft walls) or open
+    #[arg(long, default_value_t = BoundaryMode::Wrap, value_parser = clap::value_parser!(BoundaryMode))]
+    pub boundary: BoundaryMode,
+
+    /// Maximum speed of the boids in pixels per frame
+    #[arg(long, default_value_t = MAX_BOID_SPEED, value_parser = positive)]
+    pub max_speed: f64,
+
+    /// Maximum steering force in pixels per frame per frame
+    #[arg(long, default_value_t = MAX_FORCE, value_parser = positive)]
+    pub max_force: f64,
+
+    /// Steering model: force (Reynolds steering forces), turn-rate (bounded heading change) or vicsek (Vicsek model)
+    #[arg(long, default_value_t = SteeringMode::Force, value_parser = clap::value_parser!(SteeringMode))]
+    pub steering: SteeringMode,
+
+    /// Maximum turn rate in radians per second (turn-rate steering)
+    #[arg(long, default_value_t = MAX_OMEGA, value_parser = positive)]
+    pub max_turn_rate: f64,
+
+    /// Weight of the cohesion rule
+    #[arg(long, default_value_t = WEIGHT_COHESION, value_parser = finite)]
+    pub cohesion_weight: f64,
+
+    /// Weight of the separation rule
+    #[arg(long, default_value_t = WEIGHT_SEPARATION, value_parser = finite)]
+    pub separation_weight: f64,
+
+    /// Weight of the alignment rule
+    #[arg(long, default_value_t = WEIGHT_ALIGNMENT, value_parser = finite)]
+    pub alignment_weight: f64,
+
+    /// Radius of the cohesion rule in pixels
+    #[arg(long, default_value_t = FLOCK_SIZE, value_parser = non_negative)]
+    pub cohesion_radius: f64,
+
+    /// Radius of the separation rule in pixels
+    #[arg(long, default_value_t = SEPARATION_RADIUS, value_parser = non_negative)]
+    pub separation_radius: f64,
+
+    /// Radius of the alignment rule in pixels
+    #[arg(long, default_value_t = ALIGNMENT_RADIUS, value_parser = non_negative)]
+    pub alignment_radius: f64,
+
+    /// Separation falloff: average (steer away from the mean position of the neighbors), inverse or inverse-square (sum of pushes weighted by distance)
+    #[arg(long, default_value_t = SeparationFalloff::Average, value_parser = clap::value_parser!(SeparationFalloff))]
+    pub separation_falloff: SeparationFalloff,
+
+    /// Neighbor selection: metric (every boid within the radius of a rule) or topological (k nearest boids)
+    #[arg(long, default_value_t = NeighborMode::Metric, value_parser = clap::value_parser!(NeighborMode))]
+    pub neighbor_mode: NeighborMode,
+
+    /// Number of neighbors of the cohesion rule in topological mode
+    #[arg(long, default_value_t = K_COHESION)]
+    pub cohesion_k: usize,
+
+    /// Number of neighbors of the separation rule in topological mode
+    #[arg(long, default_value_t = K_SEPARATION)]
+    pub separation_k: usize,
+
+    /// Number of neighbors of the alignment rule in topological mode
+    #[arg(long, default_value_t = K_ALIGNMENT)]
+    pub alignment_k: usize,
+
+    /// Perception cone of the rules in degrees, centered on the heading (360: full circle)
+    #[arg(long, default_value_t = FIELD_OF_VIEW, value_parser = fov)]
+    pub fov: f64,
+
+    /// Perception cone of the cohesion rule in degrees (--fov if not given)
+    #[arg(long, value_parser = fov)]
+    pub cohesion_fov: Option<f64>,
+
+    /// Perception cone of the separation rule in degrees (--fov if not given)
+    #[arg(long, value_parser = fov)]
+    pub separation_fov: Option<f64>,
+
+    /// Perception cone of the alignment rule in degrees (--fov if not given)
+    #[arg(long, value_parser = fov)]
+    pub alignment_fov: Option<f64>,
+
+    /// Length of the feelers detecting the obstacles in pixels
+    #[arg(long, default_value_t = OBSTACLE_LOOKAHEAD, value_parser = non_negative)]
+    pub lookahead: f64,
+
+    /// Weight of the obstacle avoidance (in maximum forces)
+    #[arg(long, default_value_t = WEIGHT_AVOIDANCE, value_parser = finite)]
+    pub avoidance_weight: f64,
+
+    /// Number of predators hunting the flock
+    #[arg(long, default_value_t = NB_PREDATORS)]
+    pub predators: usize,
+
+    /// Speed of the predators in pixels per frame
+    #[arg(long, default_value_t = PREDATOR_SPEED, value_parser = positive)]
+    pub predator_speed: f64,
+
+    /// Maximum turn rate of the predators in radians per second
+    #[arg(long, default_value_t = PREDATOR_TURN_RATE, value_parser = positive)]
+    pub predator_turn_rate: f64,
+
+    /// Boid chased by the predators: nearest or densest (core of the densest cluster)
+    #[arg(long, default_value_t = PredatorTarget::Nearest, value_parser = clap::value_parser!(PredatorTarget))]
+    pub predator_target: PredatorTarget,
+
+    /// Weight of the flee rule
+    #[arg(long, default_value_t = WEIGHT_FLEE, value_parser = finite)]
+    pub flee_weight: f64,
+
+    /// Radius of the flee rule in pixels (distance at which a boid sees a predator)
+    #[arg(long, default_value_t = FLEE_RADIUS, value_parser = non_negative)]
+    pub flee_radius: f64,
+
+    /// Remove the boids caught by a predator
+    #[arg(long)]
+    pub capture: bool,
+
+    /// Distance at which a predator catches a boid in pixels
+    #[arg(long, default_value_t = CAPTURE_RADIUS, value_parser = non_negative)]
+    pub capture_radius: f64,
+
+    /// Point every boid seeks, as `X,Y`
+    #[arg(long, value_name = "X,Y", value_parser = point)]
+    pub goal: Option<[f64; 2]>,
+
+    /// Weight of the goal seeking rule
+    #[arg(long, default_value_t = WEIGHT_GOAL, value_parser = finite)]
+    pub goal_weight: f64,
+
+    /// Number of leaders among the boids
+    #[arg(long, default_value_t = NB_LEADERS)]
+    pub leaders: usize,
+
+    /// Where the leaders go: path (along the configured loop) or manual (after the mouse)
+    #[arg(long, default_value_t = LeaderMode::Path, value_parser = clap::value_parser!(LeaderMode))]
+    pub leader_mode: LeaderMode,
+
+    /// Weight of the follow-leader rule
+    #[arg(long, default_value_t = WEIGHT_FOLLOW, value_parser = finite)]
+    pub follow_weight: f64,
+
+    /// Radius of the follow-leader rule in pixels
+    #[arg(long, default_value_t = FOLLOW_RADIUS, value_parser = non_negative)]
+    pub follow_radius: f64,
+
+    /// Weight of the wander rule (0: off)
+    #[arg(long, default_value_t = WEIGHT_WANDER, value_parser = finite)]
+    pub wander_weight: f64,
+
+    /// Strength of the angular noise: the heading turns by up to +/- noise/2 radians per frame (0: off)
+    #[arg(long, default_value_t = NOISE, value_parser = non_negative)]
+    pub noise: f64,
+}
+
+impl Cli {
+    /**
+     * Parse the command line and build the configuration of the run
+     * Options given on the command line override the configuration file (or preset),
+     * which overrides the default values
+     */
+    pub fn load() -> Result<(Cli, Config), ConfigError> {
+        let matches = Cli::command().get_matches();
+        let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+        let mut config = match (&cli.config, &cli.preset) {
+            (Some(path), _) => Config::load(path)?,
+            (None, Some(name)) => Config::preset(name)?,
+            (None, None) => Config::default(),
+        };
+        if cli.seed.is_some() {
+            config.seed = cli.seed;
+        }
+        cli.override_params(&matches, &mut config.params);
+        Ok((cli, config))
+    }
+
+    // Replace the parameters explicitly given on the command line
+    fn override_params(&self, matches: &ArgMatches, params: &mut SimParams) {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        if given("width") { params.width = self.width; }
+        if given("height") { params.height = self.height; }
+        if given("fps") { params.frame_rate = self.fps; }
+        if given("boids") { params.nb_boids = self.boids; }
+        if given("species") { params.nb_species = self.species; }
+        if given("boundary") { params.boundary = self.boundary; }
+        if given("max_speed") { params.max_speed = self.max_speed; }
+        if given("max_force") { params.max_force = self.max_force; }
+        if given("steering") { params.steering = self.steering; }
+        if given("max_turn_rate") { params.max_turn_rate = self.max_turn_rate; }
+
+        if given("cohesion_weight") { params.weight_cohesion = self.cohesion_weight; }
+        if given("separation_weight") { params.weight_separation = self.separation_weight; }
+        if given("alignment_weight") { params.weight_alignment = self.alignment_weight; }
+
+        if given("cohesion_radius") { params.radius_cohesion = self.cohesion_radius; }
+        if given("separation_radius") { params.radius_separation = self.separation_radius; }
+        if given("alignment_radius") { params.radius_alignment = self.alignment_radius; }
+
+        if given("separation_falloff") { params.separation_falloff = self.separation_falloff; }
+        if given("neighbor_mode") { params.neighbor_mode = self.neighbor_mode; }
+        if given("cohesion_k") { params.k_cohesion = self.cohesion_k; }
+        if given("separation_k") { params.k_separation = self.separation_k; }
+        if given("alignment_k") { params.k_alignment = self.alignment_k; }
+
+        if given("fov") { params.fov = self.fov; }
+        if given("cohesion_fov") { params.fov_cohesion = self.cohesion_fov; }
+        if given("separation_fov") { params.fov_separation = self.separation_fov; }
+        if given("alignment_fov") { params.fov_alignment = self.alignment_fov; }
+
+        if given("lookahead") { params.obstacle_lookahead = self.lookahead; }
+        if given("avoidance_weight") { params.weight_avoidance = self.avoidance_weight; }
+
+        if given("predators") { params.nb_predators = self.predators; }
+        if given("predator_speed") { params.predator_speed = self.predator_speed; }
+        if given("predator_turn_rate") { params.predator_turn_rate = self.predator_turn_rate; }
+        if given("predator_target") { params.predator_target = self.predator_target; }
+        if given("flee_weight") { params.weight_flee = self.flee_weight; }
+        if given("flee_radius") { params.radius_flee = self.flee_radius; }
+        if given("capture") { params.capture = self.capture; }
+        if given("capture_radius") { params.capture_radius = self.capture_radius; }
+
+        if given("goal") { params.goal = self.goal; }
+        if given("goal_weight") { params.weight_goal = self.goal_weight; }
+
+        if given("leaders") { params.nb_leaders = self.leaders; }
+        if given("leader_mode") { params.leader_mode = self.leader_mode; }
+        if given("follow_weight") { params.weight_follow = self.follow_weight; }
+        if given("follow_radius") { params.radius_follow = self.follow_radius; }
+
+        if given("wander_weight") { params.weight_wander = self.wander_weight; }
+        if given("noise") { params.noise = self.noise; }
+    }
+}
+
+// + Value parsers +
+fn finite(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| format!("`{}` is not a number", s))?;
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(format!("`{}` is not a finite number", s))
+    }
+}
+
+fn non_negative(s: &str) -> Result<f64, String> {
+    let value = finite(s)?;
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("`{}` must be positive or zero", s))
+    }
+}
+
+fn positive(s: &str) -> Result<f64, String> {
+    let value = finite(s)?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("`{}` must be strictly positive", s))
+    }
+}
+fn fov(s: &str) -> Result<f64, String> {
+    let value = positive(s)?;
+    if value <= 360.0 {
+        Ok(value)
+    } else {
+        Err(format!("`{}` is more than 360 degrees", s))
+    }
+}
+
+fn point(s: &str) -> Result<[f64; 2], String> {
+    match s.split_once(',') {
+        Some((x, y)) => Ok([finite(x.trim())?, finite(y.trim())?]),
+        None => Err(format!("`{}` is not a point (expected X,Y)", s)),
+    }
+}
+// - Value parsers -
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::attractor::Attractor;
+use crate::obstacle::Obstacle;
+use crate::simulation::SimParams;
+use crate::species::Species;
+
+// + Built-in presets +
+const PRESETS: [(&str, &str); 4] = [
+    ("tight-school", include_str!("../presets/tight-school.toml")),
+    ("loose-swarm", include_str!("../presets/loose-swarm.toml")),
+    ("murmuration", include_str!("../presets/murmuration.toml")),
+    ("vicsek", include_str!("../presets/vicsek.toml")),
+];
+// - Built-in presets -
+
+/**
+ * Colors used by the renderer (RGBA, 0.0 to 1.0)
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    pub background: [f32; 4],
+    pub border: [f32; 4],
+    pub boid: [f32; 4],
+    pub text: [f32; 4],
+    pub obstacle: [f32; 4],
+    pub predator: [f32; 4],
+    pub attractor: [f32; 4],
+    pub repeller: [f32; 4],
+    pub goal: [f32; 4],
+    pub leader: [f32; 4],
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            background: [0.0, 0.0, 0.0, 1.0],
+            border: [0.0, 0.0, 1.0, 1.0],
+            boid: [1.0, 1.0, 1.0, 1.0],
+            text: [1.0, 1.0, 1.0, 1.0],
+            obstacle: [0.5, 0.5, 0.5, 1.0],
+            predator: [1.0, 0.0, 0.0, 1.0],
+            attractor: [0.0, 1.0, 0.0, 1.0],
+            repeller: [1.0, 0.0, 1.0, 1.0],
+            goal: [1.0, 1.0, 0.0, 1.0],
+            leader: [1.0, 0.84, 0.0, 1.0],
+        }
+    }
+}
+
+/**
+ * Content of a configuration file (TOML or JSON)
+ * Every field is optional, missing values keep their default.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub seed: Option<u64>,  // Seed of the simulation RNG (random if not given)
+    pub params: SimParams,  // World size, boid count, rule weights and radii
+    pub colors: Colors,     // Colors used by the renderer
+    pub obstacles: Vec<Obstacle>, // Static obstacles (`[[obstacles]]` tables)
+    pub attractors: Vec<Attractor>, // Attractors and repellers (`[[attractors]]` tables)
+    pub species: Vec<Species>, // Settings of each species (`[[species]]` tables)
+    pub path: Vec<[f64; 2]>, // Closed path of the leaders (`path = [[x, y], ...]`, a loop around the center if empty)
+    pub keys: BTreeMap<String, String>, // Keybindings (action name -> key name), read by the front-end
+}
+
+/**
+ * Error raised while loading a configuration
+ */
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    UnknownPreset(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "cannot read the configuration: {}", e),
+            ConfigError::Toml(e) => write!(f, "invalid TOML configuration: {}", e),
+            ConfigError::Json(e) => write!(f, "invalid JSON configuration: {}", e),
+            ConfigError::UnknownPreset(name) => write!(f, "unknown preset `{}` (available: {})", name, Config::preset_names().join(", ")),
+            ConfigError::Invalid(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // Load a configuration file (JSON if the extension is `.json`, TOML otherwise)
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            Config::from_json(&text)
+        } else {
+            Config::from_toml(&text)
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(ConfigError::Toml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_json(text: &str) -> Result<Config, ConfigError> {
+        let config: Config = serde_json::from_str(text).map_err(ConfigError::Json)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Built-in preset by name (see `preset_names`)
+    pub fn preset(name: &str) -> Result<Config, ConfigError> {
+        match PRESETS.iter().find(|(preset, _)| *preset == name) {
+            Some((_, text)) => Config::from_toml(text),
+            None => Err(ConfigError::UnknownPreset(name.to_string())),
+        }
+    }
+
+    // Names of the built-in presets
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.params.validate().map_err(ConfigError::Invalid)?;
+        for obstacle in &self.obstacles {
+            obstacle.validate().map_err(ConfigError::Invalid)?;
+        }
+        for attractor in &self.attractors {
+            attractor.validate().map_err(ConfigError::Invalid)?;
+        }
+        for species in &self.species {
+            species.validate().map_err(ConfigError::Invalid)?;
+        }
+        if self.path.iter().any(|p| !(p[0].is_finite() && p[1].is_finite())) {
+            return Err(ConfigError::Invalid("the points of the leader path must be finite".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_preset_loads() {
+        for name in Config::preset_names() {
+            if let Err(error) = Config::preset(name) {
+                panic!("preset `{}` : {}", name, error);
+            }
+        }
+        assert!(matches!(Config::preset("nope"), Err(ConfigError::UnknownPreset(_))));
+    }
+}
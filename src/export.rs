@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::boid::Boid;
+use crate::metrics::Metrics;
+
+/**
+ * Writes the boids of each step as CSV rows for post-processing
+ * (one row per boid and per step)
+ */
+pub struct CsvWriter {
+    out: BufWriter<File>,
+}
+
+impl CsvWriter {
+    pub fn create(path: &Path) -> io::Result<CsvWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "step,id,x,y,vx,vy,angle,neighbors_cohesion,neighbors_separation,neighbors_alignment")?;
+        Ok(CsvWriter { out })
+    }
+
+    // Append the rows of a step
+    pub fn write_step(&mut self, step: u64, boids: &[Boid]) -> io::Result<()> {
+        for boid in boids {
+            writeln!(
+                self.out,
+                "{},{},{},{},{},{},{},{},{},{}",
+                step,
+                boid.id,
+                boid.x,
+                boid.y,
+                boid.velocity[0],
+                boid.velocity[1],
+                boid.angle,
+                boid.nb_neighbors[0],
+                boid.nb_neighbors[1],
+                boid.nb_neighbors[2],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/**
+ * Writes the flock metrics of each step as CSV rows (one row per step)
+ */
+pub struct MetricsCsvWriter {
+    out: BufWriter<File>,
+}
+
+impl MetricsCsvWriter {
+    pub fn create(path: &Path) -> io::Result<MetricsCsvWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "step,polarization,milling,mean_nn_distance,group_radius")?;
+        Ok(MetricsCsvWriter { out })
+    }
+
+    // Append the row of a step
+    pub fn write_step(&mut self, step: u64, metrics: &Metrics) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{},{}",
+            step,
+            metrics.polarization,
+            metrics.milling,
+            metrics.mean_nn_distance,
+            metrics.group_radius,
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
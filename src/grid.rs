@@ -0,0 +1,217 @@
+use crate::boid::Boid;
+
+// + Limits of the number of cells (small radii over a large world must not allocate gigabytes) +
+const CELLS_PER_BOID: usize = 4; // Cells allowed per boid
+const MIN_MAX_CELLS: usize = 1024; // Cells always allowed, whatever the number of boids
+// - Limits of the number of cells -
+
+/**
+ * Uniform grid over the world used to find the neighbors of a boid
+ * without scanning the whole flock.
+ * Boids are bucketed by cell (counting sort), so a query only visits the
+ * cells overlapping the square around the searched circle.
+ * In a wrapping world, queries continue across the edges.
+ * The buffers are kept between rebuilds to avoid allocations.
+ */
+#[derive(Default)]
+pub struct SpatialGrid {
+    cell_size: f64,
+    requested_size: f64,    // Cell size asked at the last rebuild (before the bound on the number of cells)
+    cols: usize,
+    rows: usize,
+    width: f64,
+    height: f64,
+    wrap: bool,             // Queries wrap around the edges of the world (torus)
+    cell_start: Vec<usize>, // Index in `indices` of the first boid of each cell (+ one past the end)
+    indices: Vec<usize>,    // Boid indices sorted by cell
+    boid_cells: Vec<usize>, // Cell of each boid (scratch buffer)
+}
+
+impl SpatialGrid {
+    pub fn new() -> SpatialGrid {
+        SpatialGrid::default()
+    }
+
+    // Size of a cell (the largest rule radius at the last rebuild, or more to bound the number of cells)
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    /**
+     * Bucket the boids in cells of `cell_size` pixels over a `width` x `height` world
+     * Boids outside of the world are stored in the closest border cell
+     * The cells grow when needed to keep about `CELLS_PER_BOID` cells per boid at most
+     */
+    pub fn rebuild(&mut self, boids: &[Boid], width: f64, height: f64, cell_size: f64, wrap: bool) {
+        self.width = width;
+        self.height = height;
+        self.wrap = wrap;
+        self.requested_size = cell_size;
+        // with these sizes `cols * rows` stays below 3 * `max_cells` + 1
+        let max_cells = (boids.len() * CELLS_PER_BOID).max(MIN_MAX_CELLS) as f64;
+        self.cell_size = cell_size.max(1.0).max((width * height / max_cells).sqrt()).max(width.max(height) / max_cells);
+        self.cols = ((width / self.cell_size).ceil() as usize).max(1);
+        self.rows = ((height / self.cell_size).ceil() as usize).max(1);
+        let nb_cells = self.cols * self.rows;
+
+        // count the boids of each cell, then turn the counts into cell ends
+        self.cell_start.clear();
+        self.cell_start.resize(nb_cells + 1, 0);
+        self.boid_cells.clear();
+        for boid in boids {
+            let cell = self.cell_index(self.col_of(boid.x), self.row_of(boid.y));
+            self.boid_cells.push(cell);
+            self.cell_start[cell] += 1;
+        }
+        for cell in 1..=nb_cells {
+            self.cell_start[cell] += self.cell_start[cell - 1];
+        }
+
+        // fill each cell from its end, which leaves `cell_start` on the cell starts
+        self.indices.clear();
+        self.indices.resize(boids.len(), 0);
+        for (i, cell) in self.boid_cells.iter().enumerate().rev() {
+            self.cell_start[*cell] -= 1;
+            self.indices[self.cell_start[*cell]] = i;
+        }
+    }
+
+    /**
+     * Whether the last rebuild was done for `nb_boids` boids with these dimensions
+     * (the boids may have moved since)
+     */
+    pub fn is_built_for(&self, nb_boids: usize, width: f64, height: f64, cell_size: f64, wrap: bool) -> bool {
+        self.indices.len() == nb_boids && self.width == width && self.height == height && self.requested_size == cell_size && self.wrap == wrap
+    }
+
+    /**
+     * Call `f` with the index of every boid whose cell overlaps the circle
+     * Candidates still have to be filtered with an exact distance check
+     */
+    pub fn query<F: FnMut(usize)>(&self, x: f64, y: f64, radius: f64, mut f: F) {
+        if self.indices.is_empty() {
+            return;
+        }
+        let radius = radius.max(0.0);
+        let cols = self.ranges(x, radius, self.width, self.cols);
+        let rows = self.ranges(y, radius, self.height, self.rows);
+        for (row_min, row_max) in rows.into_iter().flatten() {
+            for row in row_min..=row_max {
+                for (col_min, col_max) in cols.into_iter().flatten() {
+                    for col in col_min..=col_max {
+                        let cell = self.cell_index(col, row);
+                        for i in &self.indices[self.cell_start[cell]..self.cell_start[cell + 1]] {
+                            f(*i);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * Cells (first, last) covering `center` +/- `radius` along one axis of `size` pixels
+     * Without wrapping the range is clamped to the world. With wrapping the part beyond
+     * an edge continues from the other edge (second range), each cell is visited once.
+     */
+    fn ranges(&self, center: f64, radius: f64, size: f64, count: usize) -> [Option<(usize, usize)>; 2] {
+        let cell_of = |v: f64| ((v / self.cell_size).floor().max(0.0) as usize).min(count - 1);
+        let main = (cell_of(center - radius), cell_of(center + radius));
+        if !self.wrap {
+            return [Some(main), None];
+        }
+        if 2.0 * radius >= size {
+            return [Some((0, count - 1)), None];
+        }
+        if center - radius < 0.0 {
+            let first = cell_of(center - radius + size);
+            if first <= main.1 {
+                return [Some((0, count - 1)), None];
+            }
+            return [Some(main), Some((first, count - 1))];
+        }
+        if center + radius > size {
+            let last = cell_of(center + radius - size);
+            if last >= main.0 {
+                return [Some((0, count - 1)), None];
+            }
+            return [Some(main), Some((0, last))];
+        }
+        [Some(main), None]
+    }
+
+    fn col_of(&self, x: f64) -> usize {
+        ((x / self.cell_size).floor().max(0.0) as usize).min(self.cols - 1)
+    }
+
+    fn row_of(&self, y: f64) -> usize {
+        ((y / self.cell_size).floor().max(0.0) as usize).min(self.rows - 1)
+    }
+
+    fn cell_index(&self, col: usize, row: usize) -> usize {
+        row * self.cols + col
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{BoundaryMode, SimParams};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    // Every boid within `radius` of each query point is visited exactly once, as found by a full scan
+    fn check_queries(boundary: BoundaryMode, cell_size: f64) {
+        let params = SimParams { width: 500.0, height: 300.0, boundary, ..SimParams::default() };
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let boids: Vec<Boid> = (0..400).map(|i| Boid::new(i, rng.gen_range(0.0..params.width), rng.gen_range(0.0..params.height), 0.0, 0.0)).collect();
+        let mut grid = SpatialGrid::new();
+        grid.rebuild(&boids, params.width, params.height, cell_size, boundary == BoundaryMode::Wrap);
+
+        // random points, the corners and radii up to more than the world
+        let mut points: Vec<[f64; 2]> = (0..50).map(|_| [rng.gen_range(0.0..params.width), rng.gen_range(0.0..params.height)]).collect();
+        points.extend([[0.0, 0.0], [params.width, 0.0], [0.0, params.height], [params.width - 1.0, params.height - 1.0]]);
+        for point in points {
+            for radius in [0.0, 10.0, cell_size, 2.5 * cell_size, 160.0, 260.0, 600.0] {
+                let mut visits = vec![0; boids.len()];
+                grid.query(point[0], point[1], radius, |i| visits[i] += 1);
+                for (i, boid) in boids.iter().enumerate() {
+                    let offset = params.offset(point, [boid.x, boid.y]);
+                    let inside = offset[0].powi(2) + offset[1].powi(2) <= radius.powi(2);
+                    assert!(visits[i] <= 1, "boid {} visited {} times from {:?} within {}", i, visits[i], point, radius);
+                    assert!(!inside || visits[i] == 1, "boid {} missed from {:?} within {}", i, point, radius);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn query_matches_a_full_scan() {
+        for cell_size in [30.0, 70.0, 140.0] {
+            check_queries(BoundaryMode::Bounce, cell_size);
+        }
+    }
+
+    #[test]
+    fn small_radii_keep_a_bounded_number_of_cells() {
+        let boids: Vec<Boid> = (0..10).map(|i| Boid::new(i, i as f64 * 1000.0, i as f64 * 500.0, 0.0, 0.0)).collect();
+        let mut grid = SpatialGrid::new();
+        for (width, height, cell_size) in [(1_000_000.0, 1_000_000.0, 1.0), (1700.0, 800.0, 0.0), (1_000_000.0, 1.0, 1.0)] {
+            grid.rebuild(&boids, width, height, cell_size, true);
+            assert!(grid.cols * grid.rows <= 3 * MIN_MAX_CELLS + 1, "{} x {} cells", grid.cols, grid.rows);
+        }
+
+        // the larger cells still find the boids in range
+        grid.rebuild(&boids, 1_000_000.0, 1_000_000.0, 1.0, false);
+        let mut found = Vec::new();
+        grid.query(2000.0, 1000.0, 1200.0, |i| found.push(i));
+        assert!([1, 2, 3].iter().all(|i| found.contains(i)), "found {:?}", found);
+    }
+
+    #[test]
+    fn query_wraps_around_the_edges() {
+        for cell_size in [30.0, 70.0, 140.0] {
+            check_queries(BoundaryMode::Wrap, cell_size);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use piston::input::Key;
+use serde::de::value::{Error, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/**
+ * User actions that can be bound to a key
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Reset,              // Respawn the boids
+    Debug,              // Toggle the debug overlay
+    Info,               // Print the boids and the parameters
+    RestoreParams,      // Restore the parameters of the configuration
+    ToggleSteering,     // Switch between the steering models
+    ToggleBoundary,     // Switch between the boundary behaviors
+    ToggleNeighborMode, // Switch between the metric and topological neighborhoods
+    ToggleSeparation,   // Switch between the separation falloffs
+    NextSpecies,        // Select the next species for the HUD controls
+    ToggleLeaderMode,   // Send the leaders along their path or after the mouse
+    ClearObstacles,     // Remove every obstacle
+    TogglePredatorTarget, // Switch the boid chased by the predators
+    ToggleCapture,      // Turn the capture of the boids by the predators on or off
+    ClearAttractors,    // Remove every attractor and repeller
+    ToggleGoal,         // Put the goal under the mouse, or remove it
+    SaveSnapshot,       // Save the complete state to the snapshot file
+    LoadSnapshot,       // Restore the complete state from the snapshot file
+    ReplayPause,        // Pause or resume the replay
+    ReplayForward,      // Show the next recorded frame (and pause)
+    ReplayBackward,     // Show the previous recorded frame (and pause)
+    ReplayRewind,       // Go back to the first recorded frame
+    ReplayReverse,      // Play the recording backwards or forwards
+    ReplayFaster,       // Double the playback speed
+    ReplaySlower,       // Halve the playback speed
+    Decrease(&'static str),     // Decrease the weight or radius of a rule (cohesion, separation, alignment, flee)
+    ToggleMode(&'static str),   // Toggle between weight and radius for a rule
+    Increase(&'static str),     // Increase the weight or radius of a rule
+}
+
+// Name of each action in the `[keys]` table of the configuration, with its default key
+const DEFAULT_BINDINGS: [(&str, Action, Key); 38] = [
+    ("reset", Action::Reset, Key::Space),
+    ("debug", Action::Debug, Key::D),
+    ("info", Action::Info, Key::I),
+    ("restore_params", Action::RestoreParams, Key::R),
+    ("toggle_steering", Action::ToggleSteering, Key::T),
+    ("toggle_boundary", Action::ToggleBoundary, Key::W),
+    ("toggle_neighbor_mode", Action::ToggleNeighborMode, Key::N),
+    ("toggle_separation", Action::ToggleSeparation, Key::E),
+    ("next_species", Action::NextSpecies, Key::Tab),
+    ("toggle_leader_mode", Action::ToggleLeaderMode, Key::M),
+    ("clear_obstacles", Action::ClearObstacles, Key::C),
+    ("toggle_predator_target", Action::TogglePredatorTarget, Key::H),
+    ("toggle_capture", Action::ToggleCapture, Key::K),
+    ("clear_attractors", Action::ClearAttractors, Key::X),
+    ("toggle_goal", Action::ToggleGoal, Key::G),
+    ("save_snapshot", Action::SaveSnapshot, Key::S),
+    ("load_snapshot", Action::LoadSnapshot, Key::L),
+    ("replay_pause", Action::ReplayPause, Key::P),
+    ("replay_forward", Action::ReplayForward, Key::Right),
+    ("replay_backward", Action::ReplayBackward, Key::Left),
+    ("replay_rewind", Action::ReplayRewind, Key::Home),
+    ("replay_reverse", Action::ReplayReverse, Key::B),
+    ("replay_faster", Action::ReplayFaster, Key::Up),
+    ("replay_slower", Action::ReplaySlower, Key::Down),
+    ("cohesion_decrease", Action::Decrease("cohesion"), Key::NumPad7),
+    ("cohesion_toggle", Action::ToggleMode("cohesion"), Key::NumPad8),
+    ("cohesion_increase", Action::Increase("cohesion"), Key::NumPad9),
+    ("separation_decrease", Action::Decrease("separation"), Key::NumPad4),
+    ("separation_toggle", Action::ToggleMode("separation"), Key::NumPad5),
+    ("separation_increase", Action::Increase("separation"), Key::NumPad6),
+    ("alignment_decrease", Action::Decrease("alignment"), Key::NumPad1),
+    ("alignment_toggle", Action::ToggleMode("alignment"), Key::NumPad2),
+    ("alignment_increase", Action::Increase("alignment"), Key::NumPad3),
+    ("flee_decrease", Action::Decrease("flee"), Key::NumPadDivide),
+    ("flee_toggle", Action::ToggleMode("flee"), Key::NumPadMultiply),
+    ("flee_increase", Action::Increase("flee"), Key::NumPadMinus),
+    ("noise_decrease", Action::Decrease("noise"), Key::LeftBracket),
+    ("noise_increase", Action::Increase("noise"), Key::RightBracket),
+];
+
+/**
+ * Key -> action table
+ */
+pub struct Keybindings {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Keybindings {
+    /**
+     * Default bindings overridden by the `[keys]` table of the configuration
+     * Key names are the Piston ones (`Space`, `D`, `NumPad7`, `F1`...)
+     */
+    pub fn new(overrides: &BTreeMap<String, String>) -> Result<Keybindings, String> {
+        for name in overrides.keys() {
+            if !DEFAULT_BINDINGS.iter().any(|(action, _, _)| action == name) {
+                return Err(format!("unknown action `{}` in the keybindings", name));
+            }
+        }
+        let mut bindings = Vec::new();
+        for (name, action, default_key) in DEFAULT_BINDINGS {
+            let key = match overrides.get(name) {
+                Some(key_name) => parse_key(key_name)?,
+                None => default_key,
+            };
+            // a second action on the same key could never be triggered
+            // (the bindings are in the order of `DEFAULT_BINDINGS`)
+            if let Some(other) = bindings.iter().position(|(bound, _)| *bound == key) {
+                return Err(format!("key `{:?}` bound to both `{}` and `{}` in the keybindings", key, DEFAULT_BINDINGS[other].0, name));
+            }
+            bindings.push((key, action));
+        }
+        Ok(Keybindings { bindings })
+    }
+
+    // Action bound to a key (if any)
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings.iter().find(|(bound, _)| *bound == key).map(|(_, action)| *action)
+    }
+}
+
+// Piston key from its variant name
+fn parse_key(name: &str) -> Result<Key, String> {
+    let deserializer: StrDeserializer<Error> = name.into_deserializer();
+    Key::deserialize(deserializer).map_err(|_| format!("unknown key `{}` in the keybindings", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(action, key)| (action.to_string(), key.to_string())).collect()
+    }
+
+    #[test]
+    fn default_and_overridden_keys() {
+        let keys = Keybindings::new(&BTreeMap::new()).unwrap();
+        assert_eq!(keys.action(Key::Space), Some(Action::Reset));
+        let keys = Keybindings::new(&overrides(&[("debug", "F1")])).unwrap();
+        assert_eq!(keys.action(Key::F1), Some(Action::Debug));
+    }
+
+    #[test]
+    fn invalid_keybindings_are_rejected() {
+        assert!(Keybindings::new(&overrides(&[("fly", "F1")])).is_err());
+        assert!(Keybindings::new(&overrides(&[("debug", "NotAKey")])).is_err());
+        // Space already resets the boids, unless reset moves too
+        assert!(Keybindings::new(&overrides(&[("debug", "Space"), ("reset", "D")])).is_ok());
+        let error = Keybindings::new(&overrides(&[("debug", "Space")])).err().unwrap();
+        assert!(error.contains("reset") && error.contains("debug"), "{}", error);
+    }
+}
@@ -0,0 +1,112 @@
+//! Headless boids simulation.
+//!
+//! The flocking logic lives here without any dependency on a window or a
+//! graphics backend, so it can be stepped from tests, tools or CI machines.
+//! The Piston renderer in `main.rs` is a thin layer on top of [`Simulation`].
+
+pub mod attractor;
+pub mod boid;
+pub mod config;
+pub mod export;
+pub mod grid;
+pub mod metrics;
+pub mod obstacle;
+pub mod predator;
+pub mod simulation;
+pub mod species;
+pub mod trajectory;
+pub mod variation;
+
+pub use attractor::Attractor;
+pub use boid::{Boid, Candidates, Surroundings};
+pub use config::{Colors, Config, ConfigError};
+pub use export::{CsvWriter, MetricsCsvWriter};
+pub use grid::SpatialGrid;
+pub use metrics::Metrics;
+pub use obstacle::{Hit, Obstacle};
+pub use predator::{Predator, PredatorTarget};
+pub use simulation::{BoundaryMode, LeaderMode, NeighborMode, SeparationFalloff, SimParams, Simulation, Snapshot, SteeringMode};
+pub use species::Species;
+pub use trajectory::{BoidState, Trajectory, TrajectoryWriter};
+pub use variation::{Distribution, Traits, Variation};
+
+// ------------------------------ DEFAULT VALUES -------------------------------
+// (overridable at runtime through `SimParams`)
+// + Window dimensions +
+pub const WIDTH: f64 = 1700.0;
+pub const HEIGHT: f64 = 800.0;
+pub const FRAME_RATE: u64 = 24;
+// - Window dimensions -
+
+// + Border avoidance constants +
+pub const EDGE_DETECTION_DISTANCE: f64 = 50.0;
+// - Border avoidance constants -
+
+// + Obstacle avoidance constants +
+pub const OBSTACLE_LOOKAHEAD: f64 = 80.0; // Length of the feelers used to detect the obstacles
+pub const WEIGHT_AVOIDANCE: f64 = 3.0; // Weight of the obstacle avoidance (in maximum forces)
+pub const OBSTACLE_RADIUS: f64 = 30.0; // Radius of the obstacles placed with the mouse
+pub const FEELER_ANGLE: f64 = 0.5; // Angle (radians) between the central feeler and the two side ones
+// - Obstacle avoidance constants -
+
+// + Attractor constants +
+pub const ATTRACTOR_STRENGTH: f64 = 1.0; // Strength of the attractors placed with the mouse (negated for the repellers)
+pub const ATTRACTOR_RADIUS: f64 = 300.0; // Falloff radius of the attractors placed with the mouse
+pub const WEIGHT_GOAL: f64 = 0.5; // Weight of the goal seeking rule
+// - Attractor constants -
+
+// + Predator constants +
+pub const NB_PREDATORS: usize = 0; // Number of predators to generate
+pub const PREDATOR_SPEED: f64 = 11.0; // Speed of the predators (pixels per frame)
+pub const PREDATOR_TURN_RATE: f64 = 2.0; // Maximum turn rate of the predators (radians per second)
+pub const FLEE_RADIUS: f64 = 150.0; // Radius of the flee rule (distance at which a boid sees a predator)
+pub const WEIGHT_FLEE: f64 = 1.5; // Weight of the flee rule
+pub const CAPTURE_RADIUS: f64 = 8.0; // Distance at which a predator catches a boid (if capture is on)
+// - Predator constants -
+
+// + Leader constants +
+pub const NB_LEADERS: usize = 0; // Number of leaders among the boids
+pub const WEIGHT_FOLLOW: f64 = 1.5; // Weight of the follow-leader rule
+pub const FOLLOW_RADIUS: f64 = 250.0; // Distance at which a boid sees a leader
+pub const FOLLOW_DISTANCE: f64 = 30.0; // Distance behind the leader the followers steer to
+pub const WAYPOINT_RADIUS: f64 = 40.0; // Distance at which a leader moves on to the next point of its path
+pub const WEIGHT_LEAD: f64 = 1.0; // Weight of the leaders' seeking of their path or of the mouse
+// - Leader constants -
+
+// + Boid specific constants +
+pub const BOID_SIZE: f64 = 10.0; // Size of the boid
+pub const MAX_BOID_SPEED: f64 = 10.0; // Maximum speed of the boid
+pub const MAX_FORCE: f64 = 0.5; // Maximum steering force (change of velocity per frame)
+pub const NB_BOIDS: usize = 30; // Number of boids to generate
+
+pub const MAX_OMEGA: f64 = 3.0; // Maximum angular velocity (radians per second) in turn-rate steering mode
+// - Boid specific constants -
+
+// + Wander and noise constants +
+pub const WEIGHT_WANDER: f64 = 0.0; // Weight of the wander rule (0 : off)
+pub const WANDER_DISTANCE: f64 = 60.0; // Distance of the wander circle ahead of the boid
+pub const WANDER_RADIUS: f64 = 30.0; // Radius of the wander circle
+pub const WANDER_JITTER: f64 = 0.3; // Maximum change of the wander target on its circle (radians per frame)
+pub const NOISE: f64 = 0.0; // Strength of the angular noise (the heading turns by up to +/- NOISE / 2 radians per frame)
+// - Wander and noise constants -
+
+// + Default boids simulation parameters +
+pub const FLOCK_SIZE: f64 = 170.; // Radius of the flock (flock is size of the circle around the boid for the cohesion rule)
+pub const WEIGHT_COHESION: f64 = 0.3; // Weight of the cohesion rule
+
+pub const SEPARATION_RADIUS: f64 = 20.0; // Radius of the separation rule
+pub const WEIGHT_SEPARATION: f64 = 0.9; // Weight of the separation rule
+
+pub const ALIGNMENT_RADIUS: f64 = 200.; // Radius of the alignment rule
+pub const WEIGHT_ALIGNMENT: f64 = 0.7; // Weight of the alignment rule
+
+pub const K_COHESION: usize = 7; // Number of neighbors of the cohesion rule in topological mode
+pub const K_SEPARATION: usize = 3; // Number of neighbors of the separation rule in topological mode
+pub const K_ALIGNMENT: usize = 7; // Number of neighbors of the alignment rule in topological mode
+
+pub const FIELD_OF_VIEW: f64 = 360.0; // Perception cone of the rules in degrees (360 : full circle)
+// - Default boids simulation parameters -
+
+// ?? @TODO
+pub const VELCIRAPTOR_SPEED: f64 = 50.;
+pub const STEERING_FACTOR: f64 = 5.;
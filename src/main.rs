@@ -6,175 +6,334 @@ extern crate rand;
 
 use graphics::Transformed;
 use opengl_graphics::TextureSettings;
-use rand::Rng;
-use std::collections::LinkedList;
 use piston::window::WindowSettings;
 use piston::event_loop::*;
 use piston::input::*;
 use glutin_window::GlutinWindow;
 use opengl_graphics::{GlGraphics, OpenGL, GlyphCache, Filter};
-use graphics::{color};
 use graphics::Context;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
-// --------------------------------- CONSTANTS ---------------------------------
-// + Window dimensions +
-const WIDTH: f64 = 1700.0; 
-const HEIGHT: f64 = 800.0;
-const FRAME_RATE: u64 = 24;
-// - Window dimensions -
-
-// + Border avoidance constants +
-const EDGE_DETECTION_DISTANCE: f64 = 50.0;
-// - Border avoidance constants -
-
-// + Boid specific constants +
-const BOID_SIZE: f64 = 10.0; // Size of the boid
-const MAX_BOID_SPEED: f64 = 10.0; // Maximum speed of the boid
-const NB_BOIDS: i32 = 30; // Number of boids to generate
-
-const MAX_OMEGA : f64 = 0.0; // Maximum angular velocity @BUGGED
-// - Boid specific constants -
-
-// + Default boids simulation parameters +
-const FLOCK_SIZE: f64 = 170.; // Radius of the flock (flock is size of the circle around the boid for the cohesion rule)
-const WEIGHT_COHESION: f64 = 0.3; // Weight of the cohesion rule
-
-const SEPARATION_RADIUS: f64 = 20.0; // Radius of the separation rule
-const WEIGHT_SEPARATION: f64 = 0.9; // Weight of the separation rule
-
-const ALIGNMENT_RADIUS: f64 = 200.; // Radius of the alignment rule
-const WEIGHT_ALIGNMENT: f64 = 0.7; // Weight of the alignment rule
-// - Default boids simulation parameters -
-
-// ?? @TODO
-const VELCIRAPTOR_SPEED: f64 = 50.;
-const STEERING_FACTOR: f64 = 5.;
+use boids::{
+    Attractor, Boid, Colors, CsvWriter, LeaderMode, MetricsCsvWriter, NeighborMode, Obstacle, Predator, SimParams, Simulation, Snapshot, Species, SteeringMode, Trajectory,
+    TrajectoryWriter, ATTRACTOR_RADIUS, ATTRACTOR_STRENGTH, BOID_SIZE, OBSTACLE_RADIUS,
+};
 
+mod cli;
+mod keys;
+mod replay;
+use cli::Cli;
+use keys::{Action, Keybindings};
+use replay::Replay;
 
 // Font date to use for the text
 const FONT_DATA: &[u8] = include_bytes!("assets/FiraSans-Regular.ttf");
 
-// + Color constants +
-const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
-const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
-const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
-const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-const WHITE_O_5 : [f32; 4] = [1.0, 1.0, 1.0, 0.2];
-const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+// Opacity of the HUD values not selected for edition
+const INACTIVE_TEXT_ALPHA: f32 = 0.2;
 
-// - Color constants -
+/**
+ * Content of a snapshot file: the simulation and the user controls
+ */
+#[derive(Serialize, Deserialize)]
+struct AppSnapshot {
+    simulation: Snapshot,
+    #[serde(deserialize_with = "deserialize_modes")]
+    modes: [bool; 4],
+}
+
+// Read the user modes (snapshots written before the flee rule only have three, its mode starts as at launch)
+fn deserialize_modes<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<[bool; 4], D::Error> {
+    let saved = Vec::<bool>::deserialize(deserializer)?;
+    let mut modes = [false; 4];
+    if !(3..=4).contains(&saved.len()) {
+        return Err(serde::de::Error::invalid_length(saved.len(), &"3 or 4 modes"));
+    }
+    modes[..saved.len()].copy_from_slice(&saved);
+    Ok(modes)
+}
+
+// Read a snapshot file written by `App::save_snapshot`
+fn read_snapshot(path: &Path) -> Result<AppSnapshot, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {} : {}", path.display(), e))?;
+    let snapshot: AppSnapshot = serde_json::from_str(&text).map_err(|e| format!("invalid snapshot {} : {}", path.display(), e))?;
+    snapshot.simulation.params.validate().map_err(|e| format!("invalid snapshot {} : {}", path.display(), e))?;
+    Ok(snapshot)
+}
 
 /**
- * Root structure of the application
+ * Root structure of the application (Piston window on top of the simulation)
  */
 struct App {
     gl: GlGraphics,     // OpenGL drawing backend
-    boids: LinkedList<Boid>,        // List of boids in the simulation
+    sim: Simulation,        // Headless simulation (boids and parameters)
     glyph_cache: GlyphCache<'static>,   // Font to use for the text
     debug : bool,       // Debug mode (display additional information on the screen)
-    weight_cohesion : f64,      // Weight of the cohesion rule defined by the user
-    weight_separation : f64,        // Weight of the separation rule defined by the user
-    weight_alignment : f64,     // Weight of the alignment rule defined by the user
-
-    radius_cohesion : f64,      // Radius of the cohesion rule defined by the user
-    radius_separation : f64,    // Radius of the separation rule defined by the
-    radius_alignment : f64,     // Radius of the alignment rule defined by the user
+    initial_params : SimParams,     // Parameters of the loaded configuration (restored by the R key)
+    initial_species : Vec<Species>,     // Species of the loaded configuration (restored by the R key)
+    selected : usize,       // Species edited with the HUD controls
+    colors : Colors,        // Colors of the loaded configuration
+    recorder : Option<TrajectoryWriter>,    // Trajectory file the run is recorded to
+    replay : Option<Replay>,        // Recording played instead of the simulation
+    cursor : [f64; 2],      // Last position of the mouse in the window
 
     /**
      * Modes to toggle between weight and radius (user controls)
      * 0 : Cohesion
      * 1 : Separation
      * 2 : Alignment
+     * 3 : Flee
      * True : Weight
      * False : Radius
      */
-    modes : [bool; 3]
+    modes : [bool; 4]
 }
 
 /**
- * App (or simulation) methods
+ * App methods
  */
 impl App {
     fn render(&mut self, args: &RenderArgs, debug: bool) {
         use graphics::*;
 
+        let colors = self.colors;
+
         // Clear the screen
         self.gl.draw(args.viewport(), |_c, gl| {
-            clear(BLACK, gl);
+            clear(colors.background, gl);
         });
 
         //draw blue lines around the edges of the screen
+        let (width, height) = (self.sim.params.width, self.sim.params.height);
         self.gl.draw(args.viewport(), |c, gl| {
             let transform = c.transform;
             //Array of vertices for the lines
             let vertices = [
-                [0.0, 0.0, WIDTH, 0.0],
-                [0.0, 0.0, 0.0, HEIGHT],
-                [0.0, HEIGHT, WIDTH, HEIGHT],
-                [WIDTH, 0.0, WIDTH, HEIGHT],
+                [0.0, 0.0, width, 0.0],
+                [0.0, 0.0, 0.0, height],
+                [0.0, height, width, height],
+                [width, 0.0, width, height],
                 ];
             // line element
-            let line = line::Line::new(BLUE, 1.0);
+            let line = line::Line::new(colors.border, 1.0);
             // Draw the lines
             for v in &vertices {
                 line.draw(*v, &c.draw_state, transform, gl);
             }
         });
 
-        //render each boid
-        for boid in &self.boids {
-            // Simulation arguments for the boids
-            let sim_args = [self.weight_cohesion, self.weight_separation, self.weight_alignment, self.radius_cohesion, self.radius_separation, self.radius_alignment];
-            boid.render(&mut self.gl, args, debug, sim_args, self.modes);
+        //draw the obstacles
+        let obstacles = self.sim.obstacles();
+        self.gl.draw(args.viewport(), |c, gl| {
+            for obstacle in obstacles {
+                match obstacle {
+                    Obstacle::Circle { center, radius } => {
+                        let circle = ellipse::Ellipse::new(colors.obstacle);
+                        circle.draw(ellipse::circle(center[0], center[1], *radius), &c.draw_state, c.transform, gl);
+                    },
+                    Obstacle::Polygon { points } => {
+                        let shape = polygon::Polygon::new(colors.obstacle);
+                        shape.draw(points, &c.draw_state, c.transform, gl);
+                    },
+                }
+            }
+        });
+
+        //draw the attractors (with their falloff radius), the repellers and the goal
+        let attractors = self.sim.attractors();
+        let goal = self.sim.params.goal;
+        self.gl.draw(args.viewport(), |c, gl| {
+            for attractor in attractors {
+                let color = if attractor.is_repeller() { colors.repeller } else { colors.attractor };
+                let (x, y) = (attractor.position[0], attractor.position[1]);
+                let mut faded = color;
+                faded[3] *= 0.1;
+                ellipse(faded, ellipse::circle(x, y, attractor.radius), c.transform, gl);
+                ellipse(color, ellipse::circle(x, y, 5.0), c.transform, gl);
+            }
+            if let Some(goal) = goal {
+                line(colors.goal, 2.0, [goal[0] - 8.0, goal[1] - 8.0, goal[0] + 8.0, goal[1] + 8.0], c.transform, gl);
+                line(colors.goal, 2.0, [goal[0] - 8.0, goal[1] + 8.0, goal[0] + 8.0, goal[1] - 8.0], c.transform, gl);
+            }
+        });
+
+        //draw the path of the leaders
+        let path = self.sim.path();
+        if self.sim.params.nb_leaders > 0 && self.sim.params.leader_mode == LeaderMode::Path {
+            self.gl.draw(args.viewport(), |c, gl| {
+                let mut faded = colors.leader;
+                faded[3] *= 0.3;
+                for (a, b) in path.iter().zip(path.iter().cycle().skip(1)) {
+                    line(faded, 1.0, [a[0], a[1], b[0], b[1]], c.transform, gl);
+                }
+            });
         }
 
-        let nb_variables = 6;
-        for i in 0..nb_variables {
-            let mut str = String::new();
-            let mut value = 0.0;
-            let mut is_radius = false;
-            match i {
-                0 => {
-                    str = format!("Cohesion Weight : {:.2}", self.weight_cohesion);
-                    is_radius = !self.modes[0];
-                },
-                2 => {
-                    str = format!("Separation Weight : {:.2}", self.weight_separation);
-                    is_radius = !self.modes[1];
-                },
-                4 => {
-                    str = format!("Alignment Weight : {:.2}", self.weight_alignment);
-                    is_radius = !self.modes[2];
-                },
-                1 => {
-                    str = format!("Cohesion Radius : {:.2}", self.radius_cohesion);
-                    is_radius = self.modes[0];
-                },
-                3 => {
-                    str = format!("Separation Radius : {:.2}", self.radius_separation);
-                    is_radius = self.modes[1];
-                },
-                5 => {
-                    str = format!("Alignment Radius : {:.2}", self.radius_alignment);
-                    is_radius = self.modes[2];
-                },
-                _ => (),
+        //render each boid (from the recording in replay mode)
+        let replay_boids: Vec<Boid>;
+        let boids = match &self.replay {
+            Some(replay) => {
+                replay_boids = replay.boids();
+                &replay_boids
+            },
+            None => self.sim.boids(),
+        };
+        for boid in boids {
+            // color, size and radii of its species
+            let (params, size, colors) = match self.sim.species().get(boid.species) {
+                Some(species) => (species.apply(&self.sim.params), species.size(), Colors { boid: species.color(boid.species, colors.boid), ..colors }),
+                None => (self.sim.params, BOID_SIZE, colors),
+            };
+            // and its own traits
+            let params = boid.traits.apply(&params);
+            let colors = if boid.leader { Colors { boid: colors.leader, ..colors } } else { colors };
+            boid.render(&mut self.gl, args, debug, size * boid.traits.size, &params, &colors);
+        }
+        // inspector : traits of the boid closest to the mouse
+        let inspected = if debug {
+            let distance = |boid: &Boid| (boid.x - self.cursor[0]).powi(2) + (boid.y - self.cursor[1]).powi(2);
+            boids.iter().min_by(|a, b| distance(a).total_cmp(&distance(b))).map(|boid| {
+                let traits = boid.traits;
+                let lines = [
+                    format!("Boid {}   species {}", boid.id, boid.species + 1),
+                    format!("Speed x{:.2}   Perception x{:.2}   Size x{:.2}", traits.max_speed, traits.perception, traits.size),
+                    format!("Weights x{:.2} / x{:.2} / x{:.2}", traits.weight_cohesion, traits.weight_separation, traits.weight_alignment),
+                ];
+                ([boid.x + 12.0, boid.y], lines)
+            })
+        } else {
+            None
+        };
+        if let Some((position, lines)) = inspected {
+            let c = &Context::new_viewport(args.viewport());
+            for (i, line) in lines.iter().enumerate() {
+                self.draw_text(line, position[0], position[1] + 10. * i as f64, colors.text, c);
             }
+        }
+        // the predators are not recorded
+        if self.replay.is_none() {
+            for predator in self.sim.predators() {
+                predator.render(&mut self.gl, args, debug, 2.0 * BOID_SIZE, &self.sim.params, &colors);
+            }
+        }
+
+        let params = self.sim.params;
+        // the rules of the species selected for edition
+        let shown = self.sim.species()[self.selected()].apply(&params);
+        let nb_variables = 8;
+        // the radii of the three rules are replaced by k in topological mode
+        let topological = params.neighbor_mode == NeighborMode::Topological;
+        for i in 0..nb_variables {
+            let (str, is_radius) = match i {
+                0 => (format!("Cohesion Weight : {:.2}", shown.weight_cohesion), !self.modes[0]),
+                2 => (format!("Separation Weight : {:.2}", shown.weight_separation), !self.modes[1]),
+                4 => (format!("Alignment Weight : {:.2}", shown.weight_alignment), !self.modes[2]),
+                1 if topological => (format!("Cohesion K : {}", params.k_cohesion), self.modes[0]),
+                3 if topological => (format!("Separation K : {}", params.k_separation), self.modes[1]),
+                5 if topological => (format!("Alignment K : {}", params.k_alignment), self.modes[2]),
+                1 => (format!("Cohesion Radius : {:.2}", shown.radius_cohesion), self.modes[0]),
+                3 => (format!("Separation Radius : {:.2}", shown.radius_separation), self.modes[1]),
+                5 => (format!("Alignment Radius : {:.2}", shown.radius_alignment), self.modes[2]),
+                6 => (format!("Flee Weight : {:.2}", params.weight_flee), !self.modes[3]),
+                7 => (format!("Flee Radius : {:.2}", params.radius_flee), self.modes[3]),
+                _ => (String::new(), false),
+            };
             let c = &Context::new_viewport(args.viewport());
             let posx = if i % 2 == 0 { 10. } else { 150. };
             let posy = if i % 2 == 0 { 10. + 10. * i as f64 } else { 10. + 10. * (i-1) as f64 };
-            // if variable is a radius draw it in red
+            // if variable is not the one edited draw it transparent
             if is_radius {
-                self.draw_text(&str, posx, posy , WHITE_O_5, &c );
+                let mut inactive = colors.text;
+                inactive[3] *= INACTIVE_TEXT_ALPHA;
+                self.draw_text(&str, posx, posy , inactive, c );
             } else {
-                self.draw_text( &str, posx, posy, WHITE, &c );
+                self.draw_text( &str, posx, posy, colors.text, c );
+            }
+        }
+
+        // selected models
+        let c = &Context::new_viewport(args.viewport());
+        let lines = [
+            match params.steering {
+                SteeringMode::Force => format!("Steering : force (max {:.2})", params.max_force),
+                SteeringMode::TurnRate => format!("Steering : turn rate (max {:.2} rad/s)", params.max_turn_rate),
+                SteeringMode::Vicsek => format!("Steering : Vicsek (speed {:.2}, radius {:.0}, eta {:.2})", params.max_speed, params.radius_alignment, params.noise),
+            },
+            format!("Boundary : {}", params.boundary),
+            format!("Neighbors : {}", params.neighbor_mode),
+            format!("Separation : {}", params.separation_falloff),
+            {
+                let species = &self.sim.species()[self.selected()];
+                let name = if species.name.is_empty() { String::new() } else { format!(" ({})", species.name) };
+                format!("Species : {} / {}{}   speed {:.2}", self.selected() + 1, self.sim.species().len(), name, shown.max_speed)
+            },
+            match params.fovs() {
+                [cohesion, separation, alignment] if cohesion == separation && separation == alignment => format!("Field of view : {:.0} deg", cohesion),
+                [cohesion, separation, alignment] => format!("Field of view : {:.0} / {:.0} / {:.0} deg", cohesion, separation, alignment),
+            },
+            format!(
+                "Predators : {} chasing the {} boid{}",
+                self.sim.predators().len(),
+                params.predator_target,
+                if params.capture { format!(" (capture on, {} boids left)", self.sim.boids().len()) } else { String::new() },
+            ),
+            format!(
+                "Leaders : {} ({})   follow weight {:.2}   radius {:.0}",
+                self.sim.boids().iter().filter(|boid| boid.leader).count(),
+                params.leader_mode,
+                params.weight_follow,
+                params.radius_follow,
+            ),
+            format!("Wander : weight {:.2}   Noise : {:.2} rad", params.weight_wander, params.noise),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            self.draw_text(line, 300., 10. + 10. * i as f64, colors.text, c);
+        }
+
+        // goal, attractors and repellers
+        let mut lines = vec![match params.goal {
+            Some(goal) => format!("Goal : ({:.0}, {:.0})   weight {:.2}", goal[0], goal[1], params.weight_goal),
+            None => "Goal : none".to_string(),
+        }];
+        for (i, attractor) in self.sim.attractors().iter().enumerate() {
+            lines.push(format!(
+                "{} {} : ({:.0}, {:.0})   strength {:.2}   radius {:.0}",
+                if attractor.is_repeller() { "Repeller" } else { "Attractor" },
+                i + 1,
+                attractor.position[0],
+                attractor.position[1],
+                attractor.strength.abs(),
+                attractor.radius,
+            ));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            self.draw_text(line, 600., 10. + 10. * i as f64, colors.text, c);
+        }
+
+        // flock metrics (of the simulation, not of the replayed recording)
+        if self.replay.is_none() {
+            let metrics = self.sim.metrics();
+            let lines = [
+                format!("Polarization : {:.2}", metrics.polarization),
+                format!("Milling : {:.2}", metrics.milling),
+                format!("Mean NN distance : {:.1}", metrics.mean_nn_distance),
+                format!("Group radius : {:.1}", metrics.group_radius),
+            ];
+            for (i, line) in lines.iter().enumerate() {
+                self.draw_text(line, 10., 90. + 10. * i as f64, colors.text, c);
             }
         }
+
+        if let Some(status) = self.replay.as_ref().map(|replay| replay.status()) {
+            self.draw_text(&status, 10., 90., colors.text, c);
+        }
     }
 
     fn draw_text(&mut self, text: &str, x: f64, y: f64, color : [f32;4], c : &Context) {
         let transform  : [[f64; 3]; 2] = c.transform.trans(x, y);
-        let mut text_obj = graphics::Text::new_color(color, 11);
+        let text_obj = graphics::Text::new_color(color, 11);
 
         text_obj.draw(
             text,
@@ -183,125 +342,201 @@ impl App {
             transform,
             &mut self.gl,
         ).unwrap();
-        
+
     }
 
-    fn update(&mut self, _args: &UpdateArgs) {
-        // C'est dégeulasse mais je ne sais pas comment faire autrement
-        let dup_boids = self.boids.clone();
-        
-        // args for simulation update
-        let sim_args = [self.weight_cohesion, self.weight_separation, self.weight_alignment, self.radius_cohesion, self.radius_separation, self.radius_alignment];
+    fn update(&mut self, args: &UpdateArgs) {
+        if let Some(replay) = &mut self.replay {
+            replay.update();
+            return;
+        }
+        self.sim.step(args.dt);
+        self.record_frame();
+    }
 
-        //update each boid loop
-        for boid in &mut self.boids {
-            boid.update(_args, dup_boids.clone(), sim_args, self.modes);
+    // Append the current boids to the trajectory file (if recording)
+    fn record_frame(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.write_frame(self.sim.boids()) {
+                eprintln!("error: recording stopped : {}", e);
+                self.recorder = None;
+            }
         }
     }
 
 
     fn print_boids(&self) {
-        for boid in &self.boids {
+        for boid in self.sim.boids() {
             println!("x: {}, y: {}", boid.x, boid.y);
         }
     }
 
+    // Add a circle obstacle under the mouse
+    fn place_obstacle(&mut self) {
+        self.sim.add_obstacle(Obstacle::Circle { center: self.cursor, radius: OBSTACLE_RADIUS });
+    }
+
+    // Add an attractor (or a repeller) under the mouse
+    fn place_attractor(&mut self, repeller: bool) {
+        let strength = if repeller { -ATTRACTOR_STRENGTH } else { ATTRACTOR_STRENGTH };
+        self.sim.add_attractor(Attractor { position: self.cursor, strength, radius: ATTRACTOR_RADIUS });
+    }
+
+    // Put the goal under the mouse, or remove it
+    fn toggle_goal(&mut self) {
+        self.sim.params.goal = match self.sim.params.goal {
+            Some(_) => None,
+            None => Some(self.cursor),
+        };
+    }
+
+    // Species edited with the HUD controls (the species may have changed since it was selected)
+    fn selected(&self) -> usize {
+        self.selected.min(self.sim.species().len() - 1)
+    }
+
     // Reset the simulation (Not the simulation parameters)
     fn reset(&mut self) {
-        self.boids.clear();
-        self.load_boids();
+        self.sim.reset();
     }
 
-    // Load the boids in the simulation (random angles and positions)
-    fn load_boids(&mut self) {
-        for ct in 0..NB_BOIDS {
-            let mut rng = rand::thread_rng();
-            let x = rng.gen_range(0..WIDTH as i32);
-            let y = rng.gen_range(0..HEIGHT as i32);
-            let angle : f64 = rng.gen_range(0.0..2.0 * std::f64::consts::PI); 
-    
-            self.boids.push_back(Boid::new(ct, x as f64, y as f64, angle));
-        }
-    }
 
-    // Initialize the simulation
-    fn init (&mut self) {
-        self.load_boids();
+    // Save the complete state of the simulation and the user modes
+    fn save_snapshot(&self, path: &Path) -> Result<(), String> {
+        let snapshot = AppSnapshot {
+            simulation: self.sim.snapshot(),
+            modes: self.modes,
+        };
+        let text = serde_json::to_string_pretty(&snapshot).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| format!("cannot write {} : {}", path.display(), e))
     }
 
+    // Restore a state saved by `save_snapshot`
+    fn load_snapshot(&mut self, path: &Path) -> Result<(), String> {
+        let snapshot = read_snapshot(path)?;
+        self.sim.restore(snapshot.simulation);
+        self.modes = snapshot.modes;
+        Ok(())
+    }
 
-    // Print the simulation parameters (for debug)
+    // Print the simulation parameters of the selected species (for debug)
     fn print(&self) {
-        println!("weight_cohesion : {}", self.weight_cohesion);
-        println!("weight_separation : {}", self.weight_separation);
-        println!("weight_alignment : {}", self.weight_alignment);
-        println!("radius_cohesion : {}", self.radius_cohesion);
-        println!("radius_separation : {}", self.radius_separation);
-        println!("radius_alignment : {}", self.radius_alignment);
+        let params = self.sim.species()[self.selected()].apply(&self.sim.params);
+        println!("species : {}", self.selected() + 1);
+        println!("weight_cohesion : {}", params.weight_cohesion);
+        println!("weight_separation : {}", params.weight_separation);
+        println!("weight_alignment : {}", params.weight_alignment);
+        println!("radius_cohesion : {}", params.radius_cohesion);
+        println!("radius_separation : {}", params.radius_separation);
+        println!("radius_alignment : {}", params.radius_alignment);
+        println!("weight_flee : {}", self.sim.params.weight_flee);
+        println!("radius_flee : {}", self.sim.params.radius_flee);
     }
 
     // Increase the value of a simulation parameter (weight or radius)
-    // @str : the parameter to increase (cohesion, separation, alignment)
-    // @value : the value to increase (*10 for radius)
+    // @str : the parameter to increase (cohesion, separation, alignment, flee, noise)
+    // @value : the value to increase (*10 for radius, k goes up by one in topological mode)
     fn increase_value(&mut self, str : String, value : f64) {
+        // the rules are edited on the selected species, starting from the global values
+        let defaults = self.sim.params;
+        let topological = defaults.neighbor_mode == NeighborMode::Topological;
+        let selected = self.selected();
+        let species = &mut self.sim.species_mut()[selected];
         match str.as_str() {
             "cohesion" => {
-                if self.modes[0] == true {
-                    self.weight_cohesion += value;
+                if self.modes[0] {
+                    *species.weight_cohesion.get_or_insert(defaults.weight_cohesion) += value;
+                } else if topological {
+                    self.sim.params.k_cohesion += 1;
                 } else {
-                    self.radius_cohesion += value*10.;
+                    *species.radius_cohesion.get_or_insert(defaults.radius_cohesion) += value*10.;
                 }
             },
             "separation" => {
-                if self.modes[1] == true {
-                    self.weight_separation += value;
+                if self.modes[1] {
+                    *species.weight_separation.get_or_insert(defaults.weight_separation) += value;
+                } else if topological {
+                    self.sim.params.k_separation += 1;
                 } else {
-                    self.radius_separation += value*10.;
+                    *species.radius_separation.get_or_insert(defaults.radius_separation) += value*10.;
                 }
             },
             "alignment" => {
-                if self.modes[2] == true {
-                    self.weight_alignment += value;
+                if self.modes[2] {
+                    *species.weight_alignment.get_or_insert(defaults.weight_alignment) += value;
+                } else if topological {
+                    self.sim.params.k_alignment += 1;
                 } else {
-                    self.radius_alignment += value*10.;
+                    *species.radius_alignment.get_or_insert(defaults.radius_alignment) += value*10.;
                 }
             },
+            "flee" => {
+                if self.modes[3] {
+                    self.sim.params.weight_flee += value;
+                } else {
+                    self.sim.params.radius_flee += value*10.;
+                }
+            },
+            "noise" => {
+                self.sim.params.noise += value;
+            },
             _ => (),
         }
     }
 
     // Decrease the value of a simulation parameter (weight or radius)
-    // @str : the parameter to decrease (cohesion, separation, alignment)
-    // @value : the value to decrease (*10 for radius)
+    // @str : the parameter to decrease (cohesion, separation, alignment, flee, noise)
+    // @value : the value to decrease (*10 for radius, k goes down by one in topological mode)
     fn decrease_value(&mut self, str : String, value : f64) {
+        // the rules are edited on the selected species, starting from the global values
+        let defaults = self.sim.params;
+        let topological = defaults.neighbor_mode == NeighborMode::Topological;
+        let selected = self.selected();
+        let species = &mut self.sim.species_mut()[selected];
         match str.as_str() {
             "cohesion" => {
-                if self.modes[0] == true {
-                    self.weight_cohesion -= value;
+                if self.modes[0] {
+                    *species.weight_cohesion.get_or_insert(defaults.weight_cohesion) -= value;
+                } else if topological {
+                    self.sim.params.k_cohesion = self.sim.params.k_cohesion.saturating_sub(1);
                 } else {
-                    self.radius_cohesion -= value*10.;
+                    *species.radius_cohesion.get_or_insert(defaults.radius_cohesion) -= value*10.;
                 }
             },
             "separation" => {
-                if self.modes[1] == true {
-                    self.weight_separation -= value;
+                if self.modes[1] {
+                    *species.weight_separation.get_or_insert(defaults.weight_separation) -= value;
+                } else if topological {
+                    self.sim.params.k_separation = self.sim.params.k_separation.saturating_sub(1);
                 } else {
-                    self.radius_separation -= value*10.;
+                    *species.radius_separation.get_or_insert(defaults.radius_separation) -= value*10.;
                 }
             },
             "alignment" => {
-                if self.modes[2] == true {
-                    self.weight_alignment -= value;
+                if self.modes[2] {
+                    *species.weight_alignment.get_or_insert(defaults.weight_alignment) -= value;
+                } else if topological {
+                    self.sim.params.k_alignment = self.sim.params.k_alignment.saturating_sub(1);
                 } else {
-                    self.radius_alignment -= value*10.;
+                    *species.radius_alignment.get_or_insert(defaults.radius_alignment) -= value*10.;
                 }
             },
+            "flee" => {
+                if self.modes[3] {
+                    self.sim.params.weight_flee -= value;
+                } else {
+                    self.sim.params.radius_flee -= value*10.;
+                }
+            },
+            "noise" => {
+                self.sim.params.noise = (self.sim.params.noise - value).max(0.0);
+            },
             _ => (),
         }
     }
 
     // Toggle between weight and radius for a simulation parameter
-    // @str : the parameter to toggle (cohesion, separation, alignment)
+    // @str : the parameter to toggle (cohesion, separation, alignment, flee)
     // This method toggle a boolean in the app.modes array
     fn toggle_mode(&mut self, str : String) {
         match str.as_str() {
@@ -314,438 +549,50 @@ impl App {
             "alignment" => {
                 self.modes[2] = !self.modes[2];
             },
+            "flee" => {
+                self.modes[3] = !self.modes[3];
+            },
             _ => (),
         }
     }
 }
 
 
-#[derive(Clone, Copy)] 
-struct Boid {
-    id : i32,
-    x: f64,
-    y: f64,
-    dx: f64,
-    dy: f64,
-    distance_from_edges : [f64; 4],
-    // angle: f64,
-    angle : f64,
-    velocity: [f64; 2],
-    separation: [f64; 2],
-    alignment: [f64; 2],
-    cohesion: [f64; 2],
-    com: [f64; 2],
-    steer_away: [f64; 2],
-    alignement_vector: [f64; 2],
-
-    previous_angle: f64,
-    unchanged_angle: i32,
+/**
+ * Drawing of a single boid with the Piston backend
+ */
+trait Render {
+    fn render(&self, gl: &mut GlGraphics, args: &RenderArgs, debug: bool, size : f64, params : &SimParams, colors : &Colors);
 }
 
-impl Boid {
-    fn new(i : i32 , x: f64, y: f64, angle : f64) -> Boid { 
-        Boid {
-            id : i ,
-            x: x,
-            y: y,
-            dx: 0.0,
-            dy: 0.0,
-            angle: angle,
-            // velocity: [1.0, 1.0],
-            distance_from_edges: [0.0, 0.0, 0.0, 0.0],
-            velocity: [angle.cos() * VELCIRAPTOR_SPEED/FRAME_RATE as f64, angle.sin() * VELCIRAPTOR_SPEED/FRAME_RATE as f64],
-            separation: [0.0, 0.0],
-            alignment: [0.0, 0.0],
-            cohesion: [0.0, 0.0],
-            //Center Of Mass
-            com: [0.0, 0.0],
-            //Point to steer away from
-            steer_away: [0.0, 0.0],
-            //Vector to align with
-            alignement_vector: [0.0, 0.0],
-
-            previous_angle: 0.0,
-            unchanged_angle: 0,
-        }
-    }
-
-    /**
-     * Update the boid's position at each frame
-     */
-    fn update(&mut self, _args: &UpdateArgs, boids : LinkedList<Boid>, sim_args : [f64; 6], modes : [bool; 3]) {
-        //update the boid's position
-        self.update_distance_from_edges();
-        self.wrap_around();
-
-        let weight_cohesion = sim_args[0];
-        let weight_separation = sim_args[1];
-        let weight_alignment = sim_args[2];
-
-        let flock_size = sim_args[3];
-        let separation_radius = sim_args[4];    
-        let alignment_radius = sim_args[5];
-
-        
-        // self.com  = self.get_center_of_mass(boids);
-        // self.steer_away = self.get_steer_away(boids);
-        
-        self.com = self.build_rule_vectors(boids.clone(), "cohesion".to_string(), flock_size);
-        self.steer_away = self.build_rule_vectors(boids.clone(), "separation".to_string(), separation_radius);
-        self.alignement_vector = self.build_rule_vectors(boids.clone(), "alignment".to_string(), alignment_radius);
-        
-        let nb_neighbors_cohesion = self.get_nb_neighbors(boids.clone(), flock_size);
-        let nb_neighbors_separation = self.get_nb_neighbors(boids.clone(), separation_radius);
-        let nb_neighbors_alignement = self.get_nb_neighbors(boids.clone(), alignment_radius);
-
-        if nb_neighbors_cohesion > 1 {
-            self.update_cohesion();
-        } else {
-            self.cohesion = [0.0, 0.0];
-        }
-
-        if nb_neighbors_separation > 1 {
-            self.update_separation();
-        } else {
-            self.separation = [0.0, 0.0];
-        }
-
-        if nb_neighbors_alignement > 1 {
-            self.update_alignment();
-        } else {
-            self.alignment = [0.0, 0.0];
-        }
-        
-        //update the boid's velocity based on the cohesion vector
-        // self.velocity[0] += self.cohesion[0] * WEIGHT_COHESION;
-        // self.velocity[1] += self.cohesion[1] * WEIGHT_COHESION;
-
-        self.velocity[0] += self.cohesion[0] * weight_cohesion;
-        
-        // self.update_separation();
-        
-        self.velocity[0] -= self.separation[0] * weight_separation;
-        self.velocity[1] -= self.separation[1] * weight_alignment;
-        
-
-
-        let mut omega_cohesion = self.smooth_angle(self.cohesion) * weight_cohesion;
-        
-        if omega_cohesion > MAX_OMEGA {
-            omega_cohesion = MAX_OMEGA;
-        } else if omega_cohesion < -MAX_OMEGA {
-            omega_cohesion = -MAX_OMEGA;
-        }
-        {
-            let tmp = self.velocity[0];
-            self.velocity[0] += omega_cohesion * self.velocity[1];
-            self.velocity[1] -= omega_cohesion * tmp;
-        }
-
-        let mut omega_separation = self.smooth_angle(self.separation) * weight_separation;
-
-        if omega_separation > MAX_OMEGA {
-            omega_separation = MAX_OMEGA;
-        } else if omega_separation < -MAX_OMEGA {
-            omega_separation = -MAX_OMEGA;
-        }
-        {
-            let tmp = self.velocity[0];
-            self.velocity[0] += omega_separation * self.velocity[1];
-            self.velocity[1] -= omega_separation * tmp;
-        }
-
-
-        let mut  omega_alignement = self.smooth_angle(self.alignment) * weight_alignment;
-        
-        if omega_alignement > MAX_OMEGA {
-            omega_alignement = MAX_OMEGA;
-        } else if omega_alignement < -MAX_OMEGA {
-            omega_alignement = -MAX_OMEGA;
-        }
-        
-        let tmp = self.velocity[0];
-        
-        self.velocity[0] += omega_alignement * self.velocity[1];
-
-        self.velocity[1] -= omega_alignement * tmp;
-        
-
-
-        self.enforce_max_speed();
-    
-        // update boid's position
-        self.x += self.velocity[0];
-        self.y += self.velocity[1];
-        //update the boid's angle
-        self.angle = self.velocity[1].atan2(self.velocity[0]);
-
-        if self.velocity[0] == 0.0 && self.velocity[1] == 0.0 {
-            self.unchanged_angle += 1;
-        } else {
-            self.unchanged_angle = 0;
-        }
-        self.dont_be_idiot();
-
-    }   
-
-    fn build_rule_vectors(&self, boids : LinkedList<Boid>, radius_name: String , radius: f64) -> [f64; 2] {
-        let mut rule_vector = [0.0, 0.0];
-        let mut nb_neighbors = 0;
-        if radius_name == "separation" || radius_name == "cohesion" || radius_name == "alignment" {
-            for boid in boids {
-                if boid.id == self.id {
-                    continue;
-                }
-                if (radius_name == "separation" || radius_name == "cohesion") && (boid.x - self.x).powi(2) + (boid.y - self.y).powi(2) < radius.powi(2) as f64 {
-                    rule_vector[0] += boid.x;
-                    rule_vector[1] += boid.y;
-                    nb_neighbors += 1;
-                } else if radius_name == "alignment" && (boid.x - self.x).powi(2) + (boid.y - self.y).powi(2) < radius.powi(2) as f64 {
-                    rule_vector[0] += boid.velocity[0];
-                    rule_vector[1] += boid.velocity[1];
-                    nb_neighbors += 1;
-                } else {
-                    // panic!("Oops")
-                }
-            }
-            if nb_neighbors > 0 {
-                rule_vector[0] /= nb_neighbors as f64;
-                rule_vector[1] /= nb_neighbors as f64;
-            }
-        }
-        rule_vector
-    }
-
-
-    fn smooth_angle(&self, vector : [f64;2] ) -> f64 {
-        const  LIMIT_THETA : f64=  std::f64::consts::PI / 2.;
-        let mut omega = self.velocity[0] * vector[1] - self.velocity[1] * vector[0];
-        let norm = (self.velocity[0].powi(2) + self.velocity[1].powi(2)).sqrt();
-        omega /= norm;
-        //chech if norm is 0 or omega is NaN
-        if omega < 0.0 {
-            omega = -1.0;
-        } else {
-            omega = 1.0;
-        }
-        
-        // omega *= omega.min(LIMIT_THETA, (self.velocity[0] * self.alignement_vector[0] + self.velocity[1] * self.alignement_vector[1]).acos());
-        let mut tmp = self.velocity[0] * self.alignement_vector[0] + self.velocity[1] * self.alignement_vector[1];
-        tmp /= norm;
-        if (tmp).acos() > LIMIT_THETA {
-            tmp = LIMIT_THETA;
-        }
-        omega *= tmp.acos();
-        // omega *= WEIGHT_ALIGNMENT / LIMIT_THETA;
-        if norm == 0.0 || omega.is_nan() {
-            return 0.0;
-        } else {
-            return omega
-        }
-    }
-
-    fn get_nb_neighbors(&self, boids : LinkedList<Boid>, radius: f64) -> i32 {
-        let mut nb_neighbors = 0;
-        for boid in boids {
-            if boid.id == self.id {
-                continue;
-            }
-            if (boid.x - self.x).powi(2) + (boid.y - self.y).powi(2) < radius.powi(2) as f64 {
-                nb_neighbors += 1;
-            }
-        }
-        nb_neighbors
-    }
-
-    /**
-     * Change the cohesion vector to point towards the center of mass of the flock
-     */
-    fn update_cohesion(&mut self) {
-        //Update the cohesion vector to point towards the center of mass of the flock
-        self.cohesion[0] = self.com[0] - self.x;
-        self.cohesion[1] = self.com[1] - self.y;
-        //normalize the cohesion vector
-        let norm = (self.cohesion[0].powi(2) + self.cohesion[1].powi(2)).sqrt();
-        self.cohesion[0] /= norm;
-        self.cohesion[1] /= norm;    
-    }
-
-    fn update_separation(&mut self) {
-        self.separation[0] = self.steer_away[0] - self.x;
-        self.separation[1] = self.steer_away[1] - self.y;
-        //normalize the cohesion vector
-        let norm = (self.separation[0].powi(2) + self.separation[1].powi(2)).sqrt();
-        self.separation[0] /= norm;
-        self.separation[1] /= norm;
-    }
-
-    fn update_alignment(&mut self) {
-        self.alignment[0] = self.alignement_vector[0] - self.velocity[0];
-        self.alignment[1] = self.alignement_vector[1] - self.velocity[1];
-        //normalize the cohesion vector
-        let norm = (self.alignment[0].powi(2) + self.alignment[1].powi(2)).sqrt();
-        self.alignment[0] /= norm;
-        self.alignment[1] /= norm;
-    }
-
-    /**
-     * Ensure that the boid's speed does not exceed the maximum speed
-     */
-    fn enforce_max_speed(&mut self) {
-        let speed = (self.velocity[0].powi(2) + self.velocity[1].powi(2)).sqrt();
-        if speed > MAX_BOID_SPEED {
-            self.velocity[0] = self.velocity[0] * MAX_BOID_SPEED / speed;
-            self.velocity[1] = self.velocity[1] * MAX_BOID_SPEED / speed;
-        }
-    }
-
-    fn update_distance_from_edges(&mut self) {
-        //calculate the distance from the edges of the screen
-        // 0 : top
-        // 1 : right
-        // 2 : bottom
-        // 3 : left
-        self.distance_from_edges[0] = self.y;
-        self.distance_from_edges[1] = WIDTH - self.x;
-        self.distance_from_edges[2] = HEIGHT - self.y;
-        self.distance_from_edges[3] = self.x;
-
-        // get |dx| and |dy| to the edges
-        self.distance_from_edges[0] = self.distance_from_edges[0].abs();
-        self.distance_from_edges[1] = self.distance_from_edges[1].abs();
-        self.distance_from_edges[2] = self.distance_from_edges[2].abs();
-        self.distance_from_edges[3] = self.distance_from_edges[3].abs();
-        // println!("distances: {:?}", self.distance_from_edges);
-    }
-
-    fn wrap_around(&mut self) {
-        if self.x > WIDTH {
-            self.x = 0.0;
-        } else if self.x < 0.0 {
-            self.x = WIDTH;
-        }
-
-        if self.y > HEIGHT {
-            self.y = 0.0;
-        } else if self.y < 0.0 {
-            self.y = HEIGHT;
-        }
-    }
-
-
-    /**
-     * 
-     */
-    fn avoid_edges(&mut self) -> f64 {
-        let mut omega : f64 = 0.;
-        for i in 0..4 {
-            // const DEFAULT_OMEGA : f64 = 0.1;
-            if self.distance_from_edges[i] < EDGE_DETECTION_DISTANCE {
-                let mut N : [f64;2] ;
-                match i {
-                    0 => {
-                        N = [0.,1.];
-                    },
-                    1 => {
-                        N = [-1.,0.];
-                        
-                    },
-                    2 => {
-                        N = [0.,-1.];
-                    },
-                    3 => {
-                        N = [1.,0.];
-                    },
-                    _ => (panic!("Merde")),
-                }
-                if (self.velocity[0] * N[1] - self.velocity[1] * N[0]) < 0.0 {
-                    omega = -1.;
-                } else {
-                    omega = 1.;
-                }
-                omega *= 1.-(self.distance_from_edges[i] / EDGE_DETECTION_DISTANCE);
-                omega *= STEERING_FACTOR;
-                omega = omega / MAX_BOID_SPEED;
-                // self.velocity[0] += -omega * self.velocity[1];
-                // self.velocity[1] += omega * self.velocity[0];
-            }
-        }
-        if omega > MAX_OMEGA {
-            omega = MAX_OMEGA;
-        } else if omega < -MAX_OMEGA {
-            omega = -MAX_OMEGA;
-        }
-        omega
-    }
-
-    fn dont_be_idiot(&mut self) {
-        if self.unchanged_angle > 2 {
-            //slowlly bring velocity to 0
-            self.velocity[0] *= 0.99;
-            self.velocity[1] *= 0.99;
-            println!("IAM AN IDIOT {} {} {} {} ", self.velocity[0], self.velocity[1], self.unchanged_angle, self.angle);
-        }
-    }
-
-
-    fn render(&self, gl: &mut GlGraphics, args: &RenderArgs, debug: bool, sim_args : [f64; 6], modes : [bool; 3]) {
-        use graphics::*; 
-
-        let weight_cohesion = sim_args[0];
-        let weight_separation = sim_args[1];
-        let weight_alignment = sim_args[2];
+impl Render for Boid {
+    fn render(&self, gl: &mut GlGraphics, args: &RenderArgs, debug: bool, size : f64, params : &SimParams, colors : &Colors) {
+        use graphics::*;
 
-        let flock_size = sim_args[3];
-        let separation_radius = sim_args[4];
-        let alignment_radius = sim_args[5];
+        let flock_size = params.radius_cohesion;
+        let separation_radius = params.radius_separation;
+        let alignment_radius = params.radius_alignment;
 
         if debug {
-            //draw the radius of the flock around the boid
-            gl.draw(args.viewport(), |c, gl| {
-                use graphics::ellipse;
-                let transform = c.transform;
-                let pink = [0.5, 0.0, 0.5, 0.3];
-                let circle = ellipse::Ellipse::new(pink);
-                let center = [self.x - flock_size/2., self.y- flock_size/2.];
-                let radius = flock_size;
-                //ofset the radius to make the circle fit the boid's position
-                circle.draw([center[0], center[1], radius, radius], &c.draw_state, transform, gl);
-            });
-    
-            //draw the radius of the separation around the boid
-            gl.draw(args.viewport(), |c, gl| {
-                use graphics::ellipse;
-                let transform = c.transform;
-                let red = [1.0, 0.0, 0.0, 0.3];
-                let circle = ellipse::Ellipse::new(red);
-                let center = [self.x - separation_radius/2., self.y- separation_radius/2.];
-                let radius = separation_radius;
-                //ofset the radius to make the circle fit the boid's position
-                circle.draw([center[0], center[1], radius, radius], &c.draw_state, transform, gl);
-            });
-    
-            //draw the radius of the alignment around the boid
-            gl.draw(args.viewport(), |c, gl| {
-                use graphics::ellipse;
-                let transform = c.transform;
-                let green = [0.1, 0.2, 0.0, 0.3];
-                let circle = ellipse::Ellipse::new(green);
-                let center = [self.x - alignment_radius/2., self.y- alignment_radius/2.];
-                let radius = alignment_radius;
-                //ofset the radius to make the circle fit the boid's position
-                circle.draw([center[0], center[1], radius, radius], &c.draw_state, transform, gl);
-            });
+            //draw the perception cone of each rule around the boid
+            let [fov_cohesion, fov_separation, fov_alignment] = params.fovs();
+            let pink = [0.5, 0.0, 0.5, 0.3];
+            let red = [1.0, 0.0, 0.0, 0.3];
+            let green = [0.1, 0.2, 0.0, 0.3];
+            draw_cone(gl, args, pink, [self.x, self.y], self.angle, flock_size, fov_cohesion);
+            draw_cone(gl, args, red, [self.x, self.y], self.angle, separation_radius, fov_separation);
+            draw_cone(gl, args, green, [self.x, self.y], self.angle, alignment_radius, fov_alignment);
         }
 
         gl.draw(args.viewport(), |c, gl| {
             let transform = c.transform.trans(self.x, self.y);
-            let white = [1.0, 1.0, 1.0, 1.0];
-            let triangle = polygon::Polygon::new(white);
+            let triangle = polygon::Polygon::new(colors.boid);
             // Define the vertices of the triangle relative to the boid's position
+            let half = size / 2.0;
             let vertices = [
-                [-5.0, -5.0],
-                [5.0, 0.0],
-                [-5.0, 5.0],
+                [-half, -half],
+                [half, 0.0],
+                [-half, half],
             ];
             // Apply rotation to the vertices based on the boid's angle
             let rotated_vertices = vertices.iter().map(|v| {
@@ -761,10 +608,10 @@ impl Boid {
             //draw a red dot at the center of the boid
             let center = rectangle::centered_square(0.0, 0.0, 2.0);
             rectangle(color::RED, center, transform, gl);
-            
+
         });
 
-        if (debug) {
+        if debug {
             //draw a blue square on each boid.com
             gl.draw(args.viewport(), |c, gl| {
                 let transform = c.transform;
@@ -782,23 +629,166 @@ impl Boid {
                 let center = rectangle::centered_square(self.steer_away[0], self.steer_away[1], 5.0);
                 square.draw(center, &c.draw_state, transform, gl);
             });
+
+            //draw a yellow line along the obstacle avoidance
+            if self.avoidance != [0.0, 0.0] {
+                gl.draw(args.viewport(), |c, gl| {
+                    let yellow = [1.0, 1.0, 0.0, 1.0];
+                    let end = [self.x + self.avoidance[0] * params.obstacle_lookahead, self.y + self.avoidance[1] * params.obstacle_lookahead];
+                    line(yellow, 1.0, [self.x, self.y, end[0], end[1]], c.transform, gl);
+                });
+            }
         }
+    }
+}
+
 
-        
-        
+/**
+ * Draw a perception cone of `fov` degrees centered on `heading` (a disc for 360 degrees)
+ */
+fn draw_cone(gl: &mut GlGraphics, args: &RenderArgs, color: [f32; 4], center: [f64; 2], heading: f64, radius: f64, fov: f64) {
+    use graphics::*;
+
+    gl.draw(args.viewport(), |c, gl| {
+        if fov >= 360.0 {
+            ellipse(color, ellipse::circle(center[0], center[1], radius), c.transform, gl);
+            return;
+        }
+        // fan of triangles from the center along the arc
+        let nb_segments = 32;
+        let half = fov.to_radians() / 2.0;
+        let mut vertices = vec![center];
+        for i in 0..=nb_segments {
+            let angle = heading - half + 2.0 * half * i as f64 / nb_segments as f64;
+            vertices.push([center[0] + radius * angle.cos(), center[1] + radius * angle.sin()]);
+        }
+        polygon(color, &vertices, c.transform, gl);
+    });
+}
+
+impl Render for Predator {
+    fn render(&self, gl: &mut GlGraphics, args: &RenderArgs, debug: bool, size : f64, params : &SimParams, colors : &Colors) {
+        use graphics::*;
 
-    }   
+        if debug {
+            //draw the radius at which the boids flee around the predator
+            gl.draw(args.viewport(), |c, gl| {
+                let orange = [1.0, 0.5, 0.0, 0.2];
+                let circle = ellipse::Ellipse::new(orange);
+                circle.draw(ellipse::circle(self.x, self.y, params.radius_flee), &c.draw_state, c.transform, gl);
+            });
+        }
 
+        gl.draw(args.viewport(), |c, gl| {
+            let transform = c.transform.trans(self.x, self.y).rot_rad(self.angle);
+            let triangle = polygon::Polygon::new(colors.predator);
+            let half = size / 2.0;
+            let vertices = [
+                [-half, -half],
+                [half, 0.0],
+                [-half, half],
+            ];
+            triangle.draw(&vertices, &c.draw_state, transform, gl);
+        });
+    }
 }
 
 
+/**
+ * Run the simulation without a window and write every step to a CSV file
+ * (step 0 is the initial state), with the flock metrics in a second file if asked
+ */
+fn run_headless(sim: &mut Simulation, steps: u64, out: &Path, metrics_out: Option<&Path>, mut recorder: Option<TrajectoryWriter>) -> std::io::Result<()> {
+    let dt = 1.0 / sim.params.frame_rate as f64;
+    let mut csv = CsvWriter::create(out)?;
+    let mut metrics_csv = metrics_out.map(MetricsCsvWriter::create).transpose()?;
+    for step in 0..=steps {
+        if step > 0 {
+            sim.step(dt);
+        }
+        csv.write_step(step, sim.boids())?;
+        if let Some(metrics_csv) = &mut metrics_csv {
+            metrics_csv.write_step(step, &sim.metrics())?;
+        }
+        if let Some(recorder) = &mut recorder {
+            recorder.write_frame(sim.boids())?;
+        }
+    }
+    if let Some(recorder) = &mut recorder {
+        recorder.flush()?;
+    }
+    if let Some(metrics_csv) = &mut metrics_csv {
+        metrics_csv.flush()?;
+    }
+    csv.flush()
+}
+
 fn main() {
+    let (cli, config) = Cli::load().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    let keys = Keybindings::new(&config.keys).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
     let opengl = OpenGL::V3_2;
 
-    let mut rng = rand::thread_rng();
+    let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+
+    // play back a recording instead of simulating
+    let replay = cli.replay.as_ref().map(|path| match Trajectory::load(path) {
+        Ok(trajectory) => Replay::new(trajectory),
+        Err(e) => {
+            eprintln!("error: cannot read the recording {} : {}", path.display(), e);
+            std::process::exit(1);
+        },
+    });
+
+    // start from a snapshot if one is given
+    let snapshot = cli.load_snapshot.as_ref().map(|path| read_snapshot(path).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }));
+    let (mut sim, modes) = match (snapshot, &replay) {
+        (Some(snapshot), _) => (Simulation::from_snapshot(snapshot.simulation), snapshot.modes),
+        (None, Some(replay)) => (Simulation::new(replay.params(), seed), [false; 4]),
+        (None, None) => (Simulation::new(config.params, seed), [false; 4]),
+    };
+    // obstacles and attractors of the configuration (a snapshot has its own)
+    if cli.load_snapshot.is_none() {
+        for obstacle in &config.obstacles {
+            sim.add_obstacle(obstacle.clone());
+        }
+        for attractor in &config.attractors {
+            sim.add_attractor(*attractor);
+        }
+        sim.set_species(config.species.clone());
+        sim.set_path(config.path.clone());
+    }
+
+    // print the seed so that the run can be replayed with --seed
+    println!("seed : {}", sim.seed());
+
+    let params = sim.params;
+    let sim_species = sim.species().to_vec();
+    let recorder = cli.record.as_ref().map(|path| TrajectoryWriter::create(path, &params).unwrap_or_else(|e| {
+        eprintln!("error: cannot create the recording {} : {}", path.display(), e);
+        std::process::exit(1);
+    }));
+
+    if cli.headless {
+        let (steps, out) = (cli.steps.unwrap_or(0), cli.out.clone().unwrap_or_default());
+        if let Err(e) = run_headless(&mut sim, steps, &out, cli.metrics_out.as_deref(), recorder) {
+            eprintln!("error: headless run failed : {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut window: GlutinWindow = WindowSettings::new(
         "Boids",
-        [WIDTH, HEIGHT]
+        [params.width, params.height]
         )
         .exit_on_esc(true)
         .build()
@@ -809,24 +799,22 @@ fn main() {
 
     let mut app = App {
         gl: GlGraphics::new(opengl),
-        boids: LinkedList::new(),
-        glyph_cache: glyph_cache,
+        sim,
+        glyph_cache,
         debug : false,
-
-        weight_cohesion : WEIGHT_COHESION,
-        weight_separation : WEIGHT_SEPARATION,
-        weight_alignment : WEIGHT_ALIGNMENT,
-
-        radius_cohesion : FLOCK_SIZE,
-        radius_separation : SEPARATION_RADIUS,
-        radius_alignment : ALIGNMENT_RADIUS,
-
-        modes : [false, false, false],
+        initial_params : params,
+        initial_species : sim_species,
+        selected : 0,
+        colors : config.colors,
+        recorder,
+        replay,
+        cursor : [0.0, 0.0],
+
+        modes,
     };
+    app.record_frame();
 
-    app.init();
-
-    let mut events = Events::new(EventSettings::new()).ups(FRAME_RATE);
+    let mut events = Events::new(EventSettings::new()).ups(params.frame_rate);
 
     while let Some(e) = events.next(&mut window) {
         if let Some(r) = e.render_args() {
@@ -837,56 +825,129 @@ fn main() {
             app.update(&u);
         }
 
+        if let Some(position) = e.mouse_cursor_args() {
+            app.cursor = position;
+            // the leaders follow the mouse in manual mode
+            app.sim.set_leader_target(Some(position));
+        }
+
+        match e.press_args() {
+            Some(Button::Mouse(MouseButton::Left)) => app.place_attractor(false),
+            Some(Button::Mouse(MouseButton::Right)) => app.place_attractor(true),
+            Some(Button::Mouse(MouseButton::Middle)) => app.place_obstacle(),
+            _ => (),
+        }
+
         if let Some(Button::Keyboard(key)) = e.press_args() {
-            match key {
-                Key::Space => {
+            match keys.action(key) {
+                Some(Action::Reset) => {
                     app.reset();
                 },
-                Key::D => {
+                Some(Action::Debug) => {
                     app.debug = !app.debug;
                 },
-                Key::NumPad7 => {
-                    app.decrease_value("cohesion".to_string(), 0.1);
+                Some(Action::Decrease(rule)) => {
+                    app.decrease_value(rule.to_string(), 0.1);
                 },
-                Key::NumPad8 => {
-                    app.toggle_mode("cohesion".to_string());
+                Some(Action::ToggleMode(rule)) => {
+                    app.toggle_mode(rule.to_string());
                 },
-                Key::NumPad9 => {
-                    app.increase_value("cohesion".to_string(), 0.1);
+                Some(Action::Increase(rule)) => {
+                    app.increase_value(rule.to_string(), 0.1);
+                },
+                Some(Action::Info) => {
+                    app.print_boids();
+                    app.print()
                 },
-                Key::NumPad4 => {
-                    app.decrease_value("separation".to_string(), 0.1);
+                Some(Action::RestoreParams) => {
+                    app.sim.params = app.initial_params;
+                    app.sim.set_species(app.initial_species.clone());
                 },
-                Key::NumPad5 => {
-                    app.toggle_mode("separation".to_string());
+                Some(Action::ToggleSteering) => {
+                    app.sim.params.steering = app.sim.params.steering.next();
                 },
-                Key::NumPad6 => {
-                    app.increase_value("separation".to_string(), 0.1);
+                Some(Action::ToggleBoundary) => {
+                    app.sim.params.boundary = app.sim.params.boundary.next();
                 },
-                Key::NumPad1 => {
-                    app.decrease_value("alignment".to_string(), 0.1);
+                Some(Action::ToggleNeighborMode) => {
+                    app.sim.params.neighbor_mode = app.sim.params.neighbor_mode.next();
                 },
-                Key::NumPad2 => {
-                    app.toggle_mode("alignment".to_string());
+                Some(Action::ToggleLeaderMode) => {
+                    app.sim.params.leader_mode = app.sim.params.leader_mode.next();
                 },
-                Key::NumPad3 => {
-                    app.increase_value("alignment".to_string(), 0.1);
+                Some(Action::NextSpecies) => {
+                    app.selected = (app.selected() + 1) % app.sim.species().len();
                 },
-                Key::I => {
-                    app.print_boids();
-                    app.print()
+                Some(Action::ToggleSeparation) => {
+                    app.sim.params.separation_falloff = app.sim.params.separation_falloff.next();
                 },
-                Key::R => {
-                    app.weight_cohesion = WEIGHT_COHESION;
-                    app.weight_separation = WEIGHT_SEPARATION;
-                    app.weight_alignment = WEIGHT_ALIGNMENT;
-                    app.radius_cohesion = FLOCK_SIZE;
-                    app.radius_separation = SEPARATION_RADIUS;
-                    app.radius_alignment = ALIGNMENT_RADIUS;
+                Some(Action::ClearObstacles) => {
+                    app.sim.clear_obstacles();
                 },
-
-                _ => (),
+                Some(Action::TogglePredatorTarget) => {
+                    app.sim.params.predator_target = app.sim.params.predator_target.next();
+                },
+                Some(Action::ToggleCapture) => {
+                    app.sim.params.capture = !app.sim.params.capture;
+                },
+                Some(Action::ClearAttractors) => {
+                    app.sim.clear_attractors();
+                },
+                Some(Action::ToggleGoal) => {
+                    app.toggle_goal();
+                },
+                Some(Action::SaveSnapshot) => {
+                    match app.save_snapshot(&cli.snapshot) {
+                        Ok(()) => println!("snapshot saved to {}", cli.snapshot.display()),
+                        Err(e) => eprintln!("error: {}", e),
+                    }
+                },
+                Some(Action::LoadSnapshot) => {
+                    match app.load_snapshot(&cli.snapshot) {
+                        Ok(()) => println!("snapshot loaded from {}", cli.snapshot.display()),
+                        Err(e) => eprintln!("error: {}", e),
+                    }
+                },
+                Some(action) => {
+                    if let Some(replay) = &mut app.replay {
+                        match action {
+                            Action::ReplayPause => replay.paused = !replay.paused,
+                            Action::ReplayForward => replay.scrub(1),
+                            Action::ReplayBackward => replay.scrub(-1),
+                            Action::ReplayRewind => replay.rewind(),
+                            Action::ReplayReverse => replay.toggle_reverse(),
+                            Action::ReplayFaster => replay.faster(),
+                            Action::ReplaySlower => replay.slower(),
+                            _ => (),
+                        }
+                    }
+                },
+                None => (),
             }
         }
     }
-}
\ No newline at end of file
+
+    // flush the end of the recording
+    if let Some(recorder) = &mut app.recorder {
+        if let Err(e) = recorder.flush() {
+            eprintln!("error: cannot write the recording : {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_with_three_modes_still_loads() {
+        let simulation = Simulation::new(SimParams { nb_boids: 5, ..SimParams::default() }, 1).snapshot();
+        let mut json = serde_json::to_value(AppSnapshot { simulation, modes: [true, false, true, true] }).unwrap();
+        json["modes"] = serde_json::json!([true, false, true]);
+        let snapshot: AppSnapshot = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(snapshot.modes, [true, false, true, false]);
+
+        json["modes"] = serde_json::json!([true, false]);
+        assert!(serde_json::from_value::<AppSnapshot>(json).is_err());
+    }
+}
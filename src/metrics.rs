@@ -0,0 +1,172 @@
+use serde::Serialize;
+
+use crate::boid::Boid;
+use crate::grid::SpatialGrid;
+use crate::simulation::{BoundaryMode, SimParams};
+
+/**
+ * Collective motion order parameters of the flock
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Metrics {
+    pub polarization: f64,     // Norm of the mean unit heading (1 : every boid flies the same way)
+    pub milling: f64,          // Norm of the mean normalized angular momentum around the centroid (1 : the flock turns in a circle)
+    pub mean_nn_distance: f64, // Mean distance between a boid and its nearest neighbor
+    pub group_radius: f64,     // Radius of gyration around the centroid
+}
+
+impl Metrics {
+    /**
+     * Compute the metrics of a frame
+     * `grid` must have been rebuilt with these boids (used for the nearest neighbors)
+     * In a wrapping world, distances and the centroid are measured across the edges
+     */
+    pub fn compute(boids: &[Boid], grid: &SpatialGrid, params: &SimParams) -> Metrics {
+        if boids.is_empty() {
+            return Metrics::default();
+        }
+        let nb_boids = boids.len() as f64;
+
+        let centroid = if params.boundary == BoundaryMode::Wrap {
+            [
+                circular_mean(boids.iter().map(|boid| boid.x), params.width),
+                circular_mean(boids.iter().map(|boid| boid.y), params.height),
+            ]
+        } else {
+            let mut centroid = [0.0, 0.0];
+            for boid in boids {
+                centroid[0] += boid.x;
+                centroid[1] += boid.y;
+            }
+            [centroid[0] / nb_boids, centroid[1] / nb_boids]
+        };
+
+        let mut heading = [0.0, 0.0];
+        let mut momentum = 0.0;
+        let mut squared_radius = 0.0;
+        for boid in boids {
+            let speed = (boid.velocity[0].powi(2) + boid.velocity[1].powi(2)).sqrt();
+            let r = params.offset(centroid, [boid.x, boid.y]);
+            let distance = (r[0].powi(2) + r[1].powi(2)).sqrt();
+            squared_radius += distance.powi(2);
+            if speed == 0.0 {
+                continue;
+            }
+            let unit = [boid.velocity[0] / speed, boid.velocity[1] / speed];
+            heading[0] += unit[0];
+            heading[1] += unit[1];
+            if distance > 0.0 {
+                momentum += (r[0] * unit[1] - r[1] * unit[0]) / distance;
+            }
+        }
+
+        Metrics {
+            polarization: (heading[0].powi(2) + heading[1].powi(2)).sqrt() / nb_boids,
+            milling: momentum.abs() / nb_boids,
+            mean_nn_distance: mean_nn_distance(boids, grid, params),
+            group_radius: (squared_radius / nb_boids).sqrt(),
+        }
+    }
+}
+
+/**
+ * Mean of coordinates on a periodic axis of length `size`
+ * Each coordinate is mapped to an angle on a circle, so a group split by an edge
+ * gets its centroid between its two halves and not in the middle of the world
+ */
+fn circular_mean<I: Iterator<Item = f64>>(values: I, size: f64) -> f64 {
+    let mut sum = [0.0, 0.0];
+    for value in values {
+        let angle = value / size * 2.0 * std::f64::consts::PI;
+        sum[0] += angle.cos();
+        sum[1] += angle.sin();
+    }
+    let angle = sum[1].atan2(sum[0]).rem_euclid(2.0 * std::f64::consts::PI);
+    angle / (2.0 * std::f64::consts::PI) * size
+}
+
+// Mean distance to the nearest neighbor (search radius doubled until a neighbor is found)
+fn mean_nn_distance(boids: &[Boid], grid: &SpatialGrid, params: &SimParams) -> f64 {
+    if boids.len() < 2 {
+        return 0.0;
+    }
+    let world_size = params.width + params.height;
+    let mut total = 0.0;
+    for boid in boids {
+        let mut radius = grid.cell_size();
+        loop {
+            let mut nearest = f64::INFINITY;
+            grid.query(boid.x, boid.y, radius, |i| {
+                let other = &boids[i];
+                if other.id != boid.id {
+                    let offset = params.offset([boid.x, boid.y], [other.x, other.y]);
+                    nearest = nearest.min(offset[0].powi(2) + offset[1].powi(2));
+                }
+            });
+            // only the boids within the radius are sure to have been visited
+            if nearest <= radius.powi(2) || radius > world_size {
+                total += nearest.sqrt();
+                break;
+            }
+            radius *= 2.0;
+        }
+    }
+    total / boids.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compute(boids: &[Boid], params: &SimParams, cell_size: f64) -> Metrics {
+        let mut grid = SpatialGrid::new();
+        grid.rebuild(boids, params.width, params.height, cell_size, params.boundary == BoundaryMode::Wrap);
+        Metrics::compute(boids, &grid, params)
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn centroid_across_the_seam() {
+        let centroid = circular_mean([10.0, 990.0, 5.0, 995.0].into_iter(), 1000.0);
+        assert!(centroid.min(1000.0 - centroid) < 1e-9, "centroid at {}", centroid);
+        assert_close(circular_mean([100.0, 300.0].into_iter(), 1000.0), 200.0);
+
+        // a flock split by the left edge stays a small group in a wrapping world
+        let boids: Vec<Boid> = [5.0, 15.0, 985.0, 995.0].iter().enumerate().map(|(i, x)| Boid::new(i as i32, *x, 400.0, 0.0, 1.0)).collect();
+        let wrap = SimParams { width: 1000.0, height: 800.0, boundary: BoundaryMode::Wrap, ..SimParams::default() };
+        assert_close(compute(&boids, &wrap, 50.0).group_radius, 125.0_f64.sqrt());
+        let bounce = SimParams { boundary: BoundaryMode::Bounce, ..wrap };
+        assert!(compute(&boids, &bounce, 50.0).group_radius > 400.0);
+    }
+
+    #[test]
+    fn ring_turning_around_its_center_mills() {
+        let boids: Vec<Boid> = (0..36).map(|i| {
+            let angle = i as f64 * 10.0_f64.to_radians();
+            Boid::new(i, 500.0 + 100.0 * angle.cos(), 400.0 + 100.0 * angle.sin(), angle + std::f64::consts::FRAC_PI_2, 2.0)
+        }).collect();
+        let metrics = compute(&boids, &SimParams::default(), 50.0);
+        assert_close(metrics.milling, 1.0);
+        assert!(metrics.polarization < 1e-9);
+        assert_close(metrics.group_radius, 100.0);
+    }
+
+    #[test]
+    fn aligned_headings_are_polarized() {
+        let boids: Vec<Boid> = (0..20).map(|i| Boid::new(i, 100.0 + i as f64 * 37.0, 50.0 + i as f64 * 13.0, 0.7, 1.0 + i as f64)).collect();
+        let metrics = compute(&boids, &SimParams::default(), 50.0);
+        assert_close(metrics.polarization, 1.0);
+    }
+
+    #[test]
+    fn nearest_neighbor_distance_of_a_lattice() {
+        // 20 pixels between neighbors, across the edges too, found with cells much smaller
+        let params = SimParams { width: 200.0, height: 100.0, boundary: BoundaryMode::Wrap, ..SimParams::default() };
+        let boids: Vec<Boid> = (0..50).map(|i| Boid::new(i, 10.0 + (i % 10) as f64 * 20.0, 10.0 + (i / 10) as f64 * 20.0, 0.0, 1.0)).collect();
+        assert_close(compute(&boids, &params, 3.0).mean_nn_distance, 20.0);
+        assert_close(mean_nn_distance(&boids[..1], &SpatialGrid::new(), &params), 0.0);
+    }
+}
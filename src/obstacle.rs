@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * Static obstacle the boids steer around
+ * In a configuration file :
+ * `{ shape = "circle", center = [x, y], radius = r }`
+ * `{ shape = "polygon", points = [[x, y], ...] }` (at least 3 points, in any winding order)
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Obstacle {
+    Circle { center: [f64; 2], radius: f64 },
+    Polygon { points: Vec<[f64; 2]> },
+}
+
+/**
+ * Closest intersection of a feeler with an obstacle
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub distance: f64,    // Distance from the origin of the feeler
+    pub normal: [f64; 2], // Unit normal of the surface, pointing out of the obstacle
+}
+
+impl Obstacle {
+    // Check that the obstacle has a drawable shape
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Obstacle::Circle { center, radius } => {
+                if !(center[0].is_finite() && center[1].is_finite()) {
+                    return Err("the center of a circle obstacle must be finite".to_string());
+                }
+                if !(radius.is_finite() && *radius > 0.0) {
+                    return Err(format!("the radius of a circle obstacle must be strictly positive (got {})", radius));
+                }
+            },
+            Obstacle::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err(format!("a polygon obstacle needs at least 3 points (got {})", points.len()));
+                }
+                if points.iter().any(|p| !(p[0].is_finite() && p[1].is_finite())) {
+                    return Err("the points of a polygon obstacle must be finite".to_string());
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Whether a point is inside the obstacle
+    pub fn contains(&self, point: [f64; 2]) -> bool {
+        match self {
+            Obstacle::Circle { center, radius } => {
+                (point[0] - center[0]).powi(2) + (point[1] - center[1]).powi(2) < radius.powi(2)
+            },
+            Obstacle::Polygon { points } => {
+                // even-odd rule : count the edges crossed by a horizontal ray
+                let mut inside = false;
+                for (a, b) in edges(points) {
+                    if (a[1] > point[1]) != (b[1] > point[1]) {
+                        let x = a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+                        if point[0] < x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            },
+        }
+    }
+
+    // Center of the obstacle (center of the circle, mean of the polygon points)
+    pub fn center(&self) -> [f64; 2] {
+        match self {
+            Obstacle::Circle { center, .. } => *center,
+            Obstacle::Polygon { points } => {
+                let mut center = [0.0, 0.0];
+                for p in points {
+                    center[0] += p[0];
+                    center[1] += p[1];
+                }
+                [center[0] / points.len() as f64, center[1] / points.len() as f64]
+            },
+        }
+    }
+
+    /**
+     * First intersection of the segment `origin` + t * `direction` (t in 0..`length`)
+     * with the surface of the obstacle, `direction` being a unit vector
+     */
+    pub fn ray_hit(&self, origin: [f64; 2], direction: [f64; 2], length: f64) -> Option<Hit> {
+        match self {
+            Obstacle::Circle { center, radius } => {
+                // |origin + t * direction - center|^2 = radius^2
+                let to_origin = [origin[0] - center[0], origin[1] - center[1]];
+                let b = to_origin[0] * direction[0] + to_origin[1] * direction[1];
+                let c = to_origin[0].powi(2) + to_origin[1].powi(2) - radius.powi(2);
+                let discriminant = b * b - c;
+                if c < 0.0 || discriminant < 0.0 {
+                    return None;
+                }
+                let t = -b - discriminant.sqrt();
+                if t < 0.0 || t > length {
+                    return None;
+                }
+                let normal = [(to_origin[0] + t * direction[0]) / radius, (to_origin[1] + t * direction[1]) / radius];
+                Some(Hit { distance: t, normal })
+            },
+            Obstacle::Polygon { points } => {
+                let mut closest: Option<Hit> = None;
+                for (a, b) in edges(points) {
+                    let edge = [b[0] - a[0], b[1] - a[1]];
+                    let denominator = direction[0] * edge[1] - direction[1] * edge[0];
+                    if denominator == 0.0 {
+                        continue;
+                    }
+                    let to_a = [a[0] - origin[0], a[1] - origin[1]];
+                    let t = (to_a[0] * edge[1] - to_a[1] * edge[0]) / denominator;
+                    let u = (to_a[0] * direction[1] - to_a[1] * direction[0]) / denominator;
+                    if t < 0.0 || t > length || !(0.0..=1.0).contains(&u) {
+                        continue;
+                    }
+                    if closest.is_some_and(|hit| hit.distance <= t) {
+                        continue;
+                    }
+                    // normal of the edge facing the feeler
+                    let norm = (edge[0].powi(2) + edge[1].powi(2)).sqrt();
+                    let mut normal = [-edge[1] / norm, edge[0] / norm];
+                    if normal[0] * direction[0] + normal[1] * direction[1] > 0.0 {
+                        normal = [-normal[0], -normal[1]];
+                    }
+                    closest = Some(Hit { distance: t, normal });
+                }
+                closest
+            },
+        }
+    }
+}
+
+// Edges of a polygon (the last point is joined to the first one)
+fn edges(points: &[[f64; 2]]) -> impl Iterator<Item = ([f64; 2], [f64; 2])> + '_ {
+    points.iter().zip(points.iter().cycle().skip(1)).map(|(a, b)| (*a, *b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Obstacle {
+        Obstacle::Polygon { points: vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]] }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn contains() {
+        let circle = Obstacle::Circle { center: [5.0, 5.0], radius: 2.0 };
+        assert!(circle.contains([5.0, 5.0]));
+        assert!(circle.contains([6.5, 5.0]));
+        assert!(!circle.contains([7.5, 5.0]));
+
+        assert!(square().contains([5.0, 5.0]));
+        assert!(!square().contains([15.0, 5.0]));
+        assert!(!square().contains([5.0, -1.0]));
+        // concave polygon : the notch is outside
+        let notched = Obstacle::Polygon { points: vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [5.0, 5.0], [0.0, 10.0]] };
+        assert!(notched.contains([5.0, 2.0]));
+        assert!(!notched.contains([5.0, 8.0]));
+    }
+
+    #[test]
+    fn ray_hit_circle() {
+        let circle = Obstacle::Circle { center: [10.0, 0.0], radius: 2.0 };
+        let hit = circle.ray_hit([0.0, 0.0], [1.0, 0.0], 20.0).unwrap();
+        assert_close(hit.distance, 8.0);
+        assert_close(hit.normal[0], -1.0);
+        assert_close(hit.normal[1], 0.0);
+        // too short, pointing away, passing by, or starting inside
+        assert!(circle.ray_hit([0.0, 0.0], [1.0, 0.0], 5.0).is_none());
+        assert!(circle.ray_hit([0.0, 0.0], [-1.0, 0.0], 20.0).is_none());
+        assert!(circle.ray_hit([0.0, 3.0], [1.0, 0.0], 20.0).is_none());
+        assert!(circle.ray_hit([10.0, 0.0], [1.0, 0.0], 20.0).is_none());
+    }
+
+    #[test]
+    fn ray_hit_polygon() {
+        // closest edge, with the normal facing the feeler
+        let hit = square().ray_hit([-5.0, 5.0], [1.0, 0.0], 20.0).unwrap();
+        assert_close(hit.distance, 5.0);
+        assert_close(hit.normal[0], -1.0);
+        assert_close(hit.normal[1], 0.0);
+        let hit = square().ray_hit([5.0, 15.0], [0.0, -1.0], 20.0).unwrap();
+        assert_close(hit.distance, 5.0);
+        assert_close(hit.normal[1], 1.0);
+        assert!(square().ray_hit([-5.0, 5.0], [1.0, 0.0], 4.0).is_none());
+        assert!(square().ray_hit([-5.0, 15.0], [1.0, 0.0], 20.0).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::boid::Boid;
+use crate::simulation::{BoundaryMode, SimParams};
+
+/**
+ * Boid chased by the predators
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PredatorTarget {
+    #[default]
+    Nearest, // Closest boid
+    Densest, // Boid with the most boids within the cohesion radius (core of the densest cluster)
+}
+
+impl PredatorTarget {
+    // Next target (runtime toggle)
+    pub fn next(self) -> PredatorTarget {
+        match self {
+            PredatorTarget::Nearest => PredatorTarget::Densest,
+            PredatorTarget::Densest => PredatorTarget::Nearest,
+        }
+    }
+}
+
+impl fmt::Display for PredatorTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PredatorTarget::Nearest => write!(f, "nearest"),
+            PredatorTarget::Densest => write!(f, "densest"),
+        }
+    }
+}
+
+impl FromStr for PredatorTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PredatorTarget, String> {
+        match s {
+            "nearest" => Ok(PredatorTarget::Nearest),
+            "densest" => Ok(PredatorTarget::Densest),
+            _ => Err(format!("unknown predator target `{}` (nearest, densest)", s)),
+        }
+    }
+}
+
+/**
+ * Agent hunting the flock at a constant speed, with a bounded turn rate
+ */
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Predator {
+    pub id: i32,
+    pub x: f64,
+    pub y: f64,
+    pub angle: f64,
+    pub velocity: [f64; 2],
+    pub target: Option<i32>, // Id of the chased boid
+}
+
+impl Predator {
+    // Create a predator heading towards `angle` at `speed` pixels per frame
+    pub fn new(id: i32, x: f64, y: f64, angle: f64, speed: f64) -> Predator {
+        Predator {
+            id,
+            x,
+            y,
+            angle,
+            velocity: [angle.cos() * speed, angle.sin() * speed],
+            target: None,
+        }
+    }
+
+    /**
+     * Turn towards the chosen boid (at most `predator_turn_rate` radians per second)
+     * and move for a step of `dt` seconds at `predator_speed`
+     * `densities` : number of boids around each boid (only read for the densest target)
+     */
+    pub fn update(&mut self, dt: f64, boids: &[Boid], densities: &[usize], params: &SimParams) {
+        let distance = |boid: &Boid| {
+            let offset = params.offset([self.x, self.y], [boid.x, boid.y]);
+            offset[0].powi(2) + offset[1].powi(2)
+        };
+        let target = match params.predator_target {
+            PredatorTarget::Nearest => boids.iter().min_by(|a, b| distance(a).total_cmp(&distance(b))),
+            // the closest of the boids with the most neighbors
+            PredatorTarget::Densest => boids.iter().zip(densities).max_by(|(a, density_a), (b, density_b)| density_a.cmp(density_b).then(distance(b).total_cmp(&distance(a)))).map(|(boid, _)| boid),
+        };
+        self.target = target.map(|boid| boid.id);
+
+        let mut heading = self.velocity[1].atan2(self.velocity[0]);
+        if let Some(boid) = target {
+            let offset = params.offset([self.x, self.y], [boid.x, boid.y]);
+            let cross = self.velocity[0] * offset[1] - self.velocity[1] * offset[0];
+            let dot = self.velocity[0] * offset[0] + self.velocity[1] * offset[1];
+            let max_turn = params.predator_turn_rate * dt;
+            heading += cross.atan2(dot).clamp(-max_turn, max_turn);
+        }
+        self.velocity = [heading.cos() * params.predator_speed, heading.sin() * params.predator_speed];
+
+        let frames = dt * params.frame_rate as f64;
+        self.x += self.velocity[0] * frames;
+        self.y += self.velocity[1] * frames;
+        self.keep_in_world(params);
+        self.angle = self.velocity[1].atan2(self.velocity[0]);
+    }
+
+    // Edges of the world (predators bounce on soft walls too)
+    fn keep_in_world(&mut self, params: &SimParams) {
+        match params.boundary {
+            BoundaryMode::Wrap => {
+                self.x = self.x.rem_euclid(params.width);
+                self.y = self.y.rem_euclid(params.height);
+            },
+            BoundaryMode::Bounce | BoundaryMode::Steer => {
+                if self.x < 0.0 {
+                    self.velocity[0] = self.velocity[0].abs();
+                } else if self.x > params.width {
+                    self.velocity[0] = -self.velocity[0].abs();
+                }
+                if self.y < 0.0 {
+                    self.velocity[1] = self.velocity[1].abs();
+                } else if self.y > params.height {
+                    self.velocity[1] = -self.velocity[1].abs();
+                }
+                self.x = self.x.clamp(0.0, params.width);
+                self.y = self.y.clamp(0.0, params.height);
+            },
+            BoundaryMode::Open => {},
+        }
+    }
+}
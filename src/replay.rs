@@ -0,0 +1,94 @@
+use boids::{Boid, SimParams, Trajectory};
+
+// Bounds of the playback speed (in recorded frames per simulation frame)
+const MIN_REPLAY_SPEED: f64 = 0.125;
+const MAX_REPLAY_SPEED: f64 = 16.0;
+
+/**
+ * Playback of a recorded trajectory (no simulation update involved)
+ */
+pub struct Replay {
+    trajectory: Trajectory,
+    position: f64,  // Current frame (fractional while playing slower than real time)
+    speed: f64,     // Recorded frames played per update
+    reverse: bool,  // Play backwards (rewind)
+    pub paused: bool,
+}
+
+impl Replay {
+    pub fn new(trajectory: Trajectory) -> Replay {
+        Replay {
+            trajectory,
+            position: 0.0,
+            speed: 1.0,
+            reverse: false,
+            paused: false,
+        }
+    }
+
+    // Advance the playback by one update (stops at both ends of the recording)
+    pub fn update(&mut self) {
+        if self.paused {
+            return;
+        }
+        let step = if self.reverse { -self.speed } else { self.speed };
+        self.position = (self.position + step).clamp(0.0, self.last_frame() as f64);
+    }
+
+    // Parameters of the recorded run
+    pub fn params(&self) -> SimParams {
+        self.trajectory.params
+    }
+
+    // Boids of the current frame
+    pub fn boids(&self) -> Vec<Boid> {
+        match self.trajectory.frames.get(self.frame()) {
+            Some(frame) => frame.iter().map(|state| state.to_boid()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // Move by a number of frames and pause (scrubbing)
+    pub fn scrub(&mut self, frames: i64) {
+        self.paused = true;
+        let frame = (self.frame() as i64 + frames).clamp(0, self.last_frame() as i64);
+        self.position = frame as f64;
+    }
+
+    // Go back to the first frame
+    pub fn rewind(&mut self) {
+        self.position = 0.0;
+    }
+
+    pub fn toggle_reverse(&mut self) {
+        self.reverse = !self.reverse;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_REPLAY_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_REPLAY_SPEED);
+    }
+
+    // Text shown in the HUD
+    pub fn status(&self) -> String {
+        format!(
+            "Replay : frame {} / {}   speed x{}{}{}",
+            self.frame(),
+            self.last_frame(),
+            self.speed,
+            if self.reverse { "   (reverse)" } else { "" },
+            if self.paused { "   (paused)" } else { "" },
+        )
+    }
+
+    fn frame(&self) -> usize {
+        self.position.floor() as usize
+    }
+
+    fn last_frame(&self) -> usize {
+        self.trajectory.frames.len().saturating_sub(1)
+    }
+}
@@ -0,0 +1,889 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::attractor::Attractor;
+use crate::boid::{Boid, Candidates, Surroundings};
+use crate::grid::SpatialGrid;
+use crate::metrics::Metrics;
+use crate::obstacle::Obstacle;
+use crate::predator::{Predator, PredatorTarget};
+use crate::species::Species;
+use crate::variation::Variation;
+use crate::{
+    ALIGNMENT_RADIUS, CAPTURE_RADIUS, FIELD_OF_VIEW, FLEE_RADIUS, FLOCK_SIZE, FOLLOW_RADIUS, FRAME_RATE, HEIGHT, K_ALIGNMENT, K_COHESION, K_SEPARATION, MAX_BOID_SPEED, MAX_FORCE, MAX_OMEGA, NB_BOIDS,
+    NB_LEADERS, NB_PREDATORS, NOISE, OBSTACLE_LOOKAHEAD, PREDATOR_SPEED, PREDATOR_TURN_RATE, SEPARATION_RADIUS, VELCIRAPTOR_SPEED, WEIGHT_ALIGNMENT,
+    WEIGHT_AVOIDANCE, WEIGHT_COHESION, WEIGHT_FLEE, WEIGHT_FOLLOW, WEIGHT_GOAL, WEIGHT_SEPARATION, WEIGHT_WANDER, WIDTH,
+};
+
+/**
+ * How the rules change the velocity of a boid
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SteeringMode {
+    #[default]
+    Force,    // Weighted steering forces limited by `max_force` (Reynolds)
+    TurnRate, // Heading turned towards the rule targets, at most `max_turn_rate` radians per second
+    Vicsek,   // Vicsek model : constant speed, mean heading of the alignment neighbors plus noise
+}
+
+impl SteeringMode {
+    // Next mode (runtime toggle)
+    pub fn next(self) -> SteeringMode {
+        match self {
+            SteeringMode::Force => SteeringMode::TurnRate,
+            SteeringMode::TurnRate => SteeringMode::Vicsek,
+            SteeringMode::Vicsek => SteeringMode::Force,
+        }
+    }
+}
+
+impl fmt::Display for SteeringMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SteeringMode::Force => write!(f, "force"),
+            SteeringMode::TurnRate => write!(f, "turn-rate"),
+            SteeringMode::Vicsek => write!(f, "vicsek"),
+        }
+    }
+}
+
+impl FromStr for SteeringMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SteeringMode, String> {
+        match s {
+            "force" => Ok(SteeringMode::Force),
+            "turn-rate" | "turn_rate" => Ok(SteeringMode::TurnRate),
+            "vicsek" => Ok(SteeringMode::Vicsek),
+            _ => Err(format!("unknown steering mode `{}` (force, turn-rate, vicsek)", s)),
+        }
+    }
+}
+
+/**
+ * What happens to the boids at the edges of the world
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    #[default]
+    Wrap,   // Torus : a boid leaving an edge comes back from the opposite one
+    Bounce, // Reflective walls
+    Steer,  // Soft walls : the boids steer away from the edges closer than `EDGE_DETECTION_DISTANCE`
+    Open,   // Unbounded world
+}
+
+impl BoundaryMode {
+    // Next mode (runtime toggle)
+    pub fn next(self) -> BoundaryMode {
+        match self {
+            BoundaryMode::Wrap => BoundaryMode::Bounce,
+            BoundaryMode::Bounce => BoundaryMode::Steer,
+            BoundaryMode::Steer => BoundaryMode::Open,
+            BoundaryMode::Open => BoundaryMode::Wrap,
+        }
+    }
+}
+
+impl fmt::Display for BoundaryMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoundaryMode::Wrap => write!(f, "wrap"),
+            BoundaryMode::Bounce => write!(f, "bounce"),
+            BoundaryMode::Steer => write!(f, "steer"),
+            BoundaryMode::Open => write!(f, "open"),
+        }
+    }
+}
+
+impl FromStr for BoundaryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BoundaryMode, String> {
+        match s {
+            "wrap" => Ok(BoundaryMode::Wrap),
+            "bounce" => Ok(BoundaryMode::Bounce),
+            "steer" => Ok(BoundaryMode::Steer),
+            "open" => Ok(BoundaryMode::Open),
+            _ => Err(format!("unknown boundary mode `{}` (wrap, bounce, steer, open)", s)),
+        }
+    }
+}
+
+/**
+ * How the separation rule weighs the close neighbors
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeparationFalloff {
+    #[default]
+    Average,       // Steer away from the average position of the neighbors (every neighbor counts the same)
+    Inverse,       // Sum of pushes away from each neighbor, in 1 / distance
+    InverseSquare, // Sum of pushes away from each neighbor, in 1 / distance^2
+}
+
+impl SeparationFalloff {
+    // Next falloff (runtime toggle)
+    pub fn next(self) -> SeparationFalloff {
+        match self {
+            SeparationFalloff::Average => SeparationFalloff::Inverse,
+            SeparationFalloff::Inverse => SeparationFalloff::InverseSquare,
+            SeparationFalloff::InverseSquare => SeparationFalloff::Average,
+        }
+    }
+}
+
+impl fmt::Display for SeparationFalloff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeparationFalloff::Average => write!(f, "average"),
+            SeparationFalloff::Inverse => write!(f, "inverse"),
+            SeparationFalloff::InverseSquare => write!(f, "inverse-square"),
+        }
+    }
+}
+
+impl FromStr for SeparationFalloff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SeparationFalloff, String> {
+        match s {
+            "average" => Ok(SeparationFalloff::Average),
+            "inverse" => Ok(SeparationFalloff::Inverse),
+            "inverse-square" | "inverse_square" => Ok(SeparationFalloff::InverseSquare),
+            _ => Err(format!("unknown separation falloff `{}` (average, inverse, inverse-square)", s)),
+        }
+    }
+}
+
+/**
+ * Where the leaders go
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderMode {
+    #[default]
+    Path,   // Along the closed path of the simulation, point after point
+    Manual, // Towards the target set by the user (the mouse)
+}
+
+impl LeaderMode {
+    // Next mode (runtime toggle)
+    pub fn next(self) -> LeaderMode {
+        match self {
+            LeaderMode::Path => LeaderMode::Manual,
+            LeaderMode::Manual => LeaderMode::Path,
+        }
+    }
+}
+
+impl fmt::Display for LeaderMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LeaderMode::Path => write!(f, "path"),
+            LeaderMode::Manual => write!(f, "manual"),
+        }
+    }
+}
+
+impl FromStr for LeaderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LeaderMode, String> {
+        match s {
+            "path" => Ok(LeaderMode::Path),
+            "manual" => Ok(LeaderMode::Manual),
+            _ => Err(format!("unknown leader mode `{}` (path, manual)", s)),
+        }
+    }
+}
+
+/**
+ * Which neighbors a rule takes into account
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighborMode {
+    #[default]
+    Metric,      // Every boid within the radius of the rule
+    Topological, // The k nearest boids (k per rule), whatever their distance
+}
+
+impl NeighborMode {
+    // Next mode (runtime toggle)
+    pub fn next(self) -> NeighborMode {
+        match self {
+            NeighborMode::Metric => NeighborMode::Topological,
+            NeighborMode::Topological => NeighborMode::Metric,
+        }
+    }
+}
+
+impl fmt::Display for NeighborMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NeighborMode::Metric => write!(f, "metric"),
+            NeighborMode::Topological => write!(f, "topological"),
+        }
+    }
+}
+
+impl FromStr for NeighborMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NeighborMode, String> {
+        match s {
+            "metric" => Ok(NeighborMode::Metric),
+            "topological" => Ok(NeighborMode::Topological),
+            _ => Err(format!("unknown neighbor mode `{}` (metric, topological)", s)),
+        }
+    }
+}
+
+/**
+ * World settings, rule weights and radii used by every boid at each step
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimParams {
+    pub width: f64,        // Width of the world
+    pub height: f64,       // Height of the world
+    pub frame_rate: u64,   // Number of frames per second (velocities are in pixels per frame)
+    pub nb_boids: usize,   // Number of boids to generate
+    pub nb_species: usize, // Number of species the boids are spread over (at least as many as described)
+    pub variation: Variation, // Distributions of the traits of each boid (none : identical boids)
+    pub boundary: BoundaryMode, // Behavior of the boids at the edges of the world
+    pub max_speed: f64,    // Maximum speed of the boids (pixels per frame)
+    pub max_force: f64,    // Maximum steering force (pixels per frame per frame)
+    pub steering: SteeringMode, // How the rules change the velocity
+    pub max_turn_rate: f64, // Maximum change of heading in turn-rate mode (radians per second)
+
+    pub weight_cohesion: f64,   // Weight of the cohesion rule
+    pub weight_separation: f64, // Weight of the separation rule
+    pub weight_alignment: f64,  // Weight of the alignment rule
+
+    pub radius_cohesion: f64,   // Radius of the cohesion rule
+    pub radius_separation: f64, // Radius of the separation rule
+    pub radius_alignment: f64,  // Radius of the alignment rule
+
+    pub separation_falloff: SeparationFalloff, // How the separation rule weighs the neighbors by distance
+
+    pub neighbor_mode: NeighborMode, // Neighbors within the radii (metric) or k nearest (topological)
+    pub k_cohesion: usize,   // Number of neighbors of the cohesion rule in topological mode
+    pub k_separation: usize, // Number of neighbors of the separation rule in topological mode
+    pub k_alignment: usize,  // Number of neighbors of the alignment rule in topological mode
+
+    pub fov: f64, // Perception cone of the rules, centered on the heading (degrees, 360 : full circle)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fov_cohesion: Option<f64>,   // Cone of the cohesion rule (`fov` if not given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fov_separation: Option<f64>, // Cone of the separation rule (`fov` if not given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fov_alignment: Option<f64>,  // Cone of the alignment rule (`fov` if not given)
+
+    pub obstacle_lookahead: f64, // Length of the feelers detecting the obstacles
+    pub weight_avoidance: f64,   // Weight of the obstacle avoidance (in maximum forces)
+
+    pub nb_predators: usize,             // Number of predators to generate
+    pub predator_speed: f64,             // Speed of the predators (pixels per frame)
+    pub predator_turn_rate: f64,         // Maximum turn rate of the predators (radians per second)
+    pub predator_target: PredatorTarget, // Boid chased by the predators
+    pub weight_flee: f64,                // Weight of the flee rule
+    pub radius_flee: f64,                // Radius of the flee rule
+    pub capture: bool,                   // Remove the boids caught by a predator
+    pub capture_radius: f64,             // Distance at which a predator catches a boid
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub goal: Option<[f64; 2]>, // Point every boid seeks (none by default)
+    pub weight_goal: f64,       // Weight of the goal seeking rule
+
+    pub nb_leaders: usize,       // Number of leaders among the boids (the first ones)
+    pub leader_mode: LeaderMode, // Where the leaders go
+    pub weight_follow: f64,      // Weight of the follow-leader rule
+    pub radius_follow: f64,      // Radius of the follow-leader rule
+
+    pub weight_wander: f64, // Weight of the wander rule (0 : off)
+    pub noise: f64,         // Strength of the angular noise (radians per frame, 0 : off)
+}
+
+impl Default for SimParams {
+    fn default() -> SimParams {
+        SimParams {
+            width: WIDTH,
+            height: HEIGHT,
+            frame_rate: FRAME_RATE,
+            nb_boids: NB_BOIDS,
+            nb_species: 1,
+            variation: Variation::default(),
+            boundary: BoundaryMode::Wrap,
+            max_speed: MAX_BOID_SPEED,
+            max_force: MAX_FORCE,
+            steering: SteeringMode::Force,
+            max_turn_rate: MAX_OMEGA,
+
+            weight_cohesion: WEIGHT_COHESION,
+            weight_separation: WEIGHT_SEPARATION,
+            weight_alignment: WEIGHT_ALIGNMENT,
+
+            radius_cohesion: FLOCK_SIZE,
+            radius_separation: SEPARATION_RADIUS,
+            radius_alignment: ALIGNMENT_RADIUS,
+
+            separation_falloff: SeparationFalloff::Average,
+
+            neighbor_mode: NeighborMode::Metric,
+            k_cohesion: K_COHESION,
+            k_separation: K_SEPARATION,
+            k_alignment: K_ALIGNMENT,
+
+            fov: FIELD_OF_VIEW,
+            fov_cohesion: None,
+            fov_separation: None,
+            fov_alignment: None,
+
+            obstacle_lookahead: OBSTACLE_LOOKAHEAD,
+            weight_avoidance: WEIGHT_AVOIDANCE,
+
+            nb_predators: NB_PREDATORS,
+            predator_speed: PREDATOR_SPEED,
+            predator_turn_rate: PREDATOR_TURN_RATE,
+            predator_target: PredatorTarget::Nearest,
+            weight_flee: WEIGHT_FLEE,
+            radius_flee: FLEE_RADIUS,
+            capture: false,
+            capture_radius: CAPTURE_RADIUS,
+
+            goal: None,
+            weight_goal: WEIGHT_GOAL,
+
+            nb_leaders: NB_LEADERS,
+            leader_mode: LeaderMode::Path,
+            weight_follow: WEIGHT_FOLLOW,
+            radius_follow: FOLLOW_RADIUS,
+
+            weight_wander: WEIGHT_WANDER,
+            noise: NOISE,
+        }
+    }
+}
+
+impl SimParams {
+    // Largest of the rule radii (size of the neighbor grid cells)
+    pub fn max_radius(&self) -> f64 {
+        self.radius_cohesion.max(self.radius_separation).max(self.radius_alignment)
+    }
+
+    // Perception cone of each rule in degrees (cohesion, separation, alignment)
+    pub fn fovs(&self) -> [f64; 3] {
+        [
+            self.fov_cohesion.unwrap_or(self.fov),
+            self.fov_separation.unwrap_or(self.fov),
+            self.fov_alignment.unwrap_or(self.fov),
+        ]
+    }
+
+    /**
+     * Vector from `from` to `to`
+     * In a wrapping world, the shortest one across the edges (minimum image)
+     */
+    pub fn offset(&self, from: [f64; 2], to: [f64; 2]) -> [f64; 2] {
+        let mut offset = [to[0] - from[0], to[1] - from[1]];
+        if self.boundary == BoundaryMode::Wrap {
+            offset[0] -= self.width * (offset[0] / self.width).round();
+            offset[1] -= self.height * (offset[1] / self.height).round();
+        }
+        offset
+    }
+
+    // Check that the parameters can be simulated
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [("width", self.width), ("height", self.height), ("max_speed", self.max_speed), ("max_force", self.max_force), ("max_turn_rate", self.max_turn_rate), ("predator_speed", self.predator_speed), ("predator_turn_rate", self.predator_turn_rate)];
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("`{}` must be strictly positive (got {})", name, value));
+            }
+        }
+        let non_negative = [("radius_cohesion", self.radius_cohesion), ("radius_separation", self.radius_separation), ("radius_alignment", self.radius_alignment), ("obstacle_lookahead", self.obstacle_lookahead), ("radius_flee", self.radius_flee), ("capture_radius", self.capture_radius), ("radius_follow", self.radius_follow)];
+        for (name, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("`{}` must be positive or zero (got {})", name, value));
+            }
+        }
+        let finite = [("weight_cohesion", self.weight_cohesion), ("weight_separation", self.weight_separation), ("weight_alignment", self.weight_alignment), ("weight_avoidance", self.weight_avoidance), ("weight_flee", self.weight_flee), ("weight_goal", self.weight_goal), ("weight_follow", self.weight_follow), ("weight_wander", self.weight_wander)];
+        for (name, value) in finite {
+            if !value.is_finite() {
+                return Err(format!("`{}` must be a finite number (got {})", name, value));
+            }
+        }
+        let [fov_cohesion, fov_separation, fov_alignment] = self.fovs();
+        let fovs = [("fov", self.fov), ("fov_cohesion", fov_cohesion), ("fov_separation", fov_separation), ("fov_alignment", fov_alignment)];
+        for (name, fov) in fovs {
+            if !(fov.is_finite() && fov > 0.0 && fov <= 360.0) {
+                return Err(format!("`{}` must be in ]0, 360] degrees (got {})", name, fov));
+            }
+        }
+        if !(self.noise.is_finite() && self.noise >= 0.0) {
+            return Err(format!("`noise` must be positive or zero (got {})", self.noise));
+        }
+        if self.goal.is_some_and(|goal| !(goal[0].is_finite() && goal[1].is_finite())) {
+            return Err("`goal` must be a finite point".to_string());
+        }
+        if self.frame_rate == 0 {
+            return Err("`frame_rate` must be at least 1".to_string());
+        }
+        if self.nb_species == 0 {
+            return Err("`nb_species` must be at least 1".to_string());
+        }
+        self.variation.validate()?;
+        Ok(())
+    }
+}
+
+/**
+ * Complete state of a simulation (boids, predators, obstacles, attractors, parameters and RNG)
+ * Restoring a snapshot continues the run exactly where it was taken.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub params: SimParams,
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+    pub boids: Vec<Boid>,
+    #[serde(default)]
+    pub predators: Vec<Predator>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    pub attractors: Vec<Attractor>,
+    #[serde(default)]
+    pub species: Vec<Species>,
+    #[serde(default)]
+    pub path: Vec<[f64; 2]>,
+}
+
+/**
+ * Headless flocking simulation (no window, no graphics backend)
+ * All the randomness comes from an RNG seeded at creation, so the same seed,
+ * parameters and number of steps always produce bit-identical boids.
+ */
+pub struct Simulation {
+    boids: Vec<Boid>,      // Boids of the current frame (read-only during a step)
+    back: Vec<Boid>,       // Write buffer for the next frame, swapped with `boids` after each step
+    predators: Vec<Predator>, // Predators hunting the boids
+    pub params: SimParams, // Simulation parameters defined by the user
+    grid: SpatialGrid,     // Neighbor index, rebuilt at each step
+    candidates: Candidates, // Buffer of the topological neighbor search, reused at each step
+    leaders: Vec<Boid>,    // Copies of the leaders, refilled at each step
+    densities: Vec<usize>, // Number of boids around each boid (densest predator target)
+    obstacles: Vec<Obstacle>, // Static obstacles the boids steer around
+    attractors: Vec<Attractor>, // Attractors and repellers
+    species: Vec<Species>, // Species of the boids (`params.nb_species` of them)
+    path: Vec<[f64; 2]>,   // Closed path of the leaders in path mode
+    leader_target: Option<[f64; 2]>, // Point the leaders go to in manual mode
+    seed: u64,             // Seed of the RNG (to replay a run)
+    rng: ChaCha8Rng,       // RNG driving spawning and any random behavior
+    metrics: Metrics,      // Order parameters of the current frame
+}
+
+impl Simulation {
+    // Create a simulation and spawn its boids from the given seed
+    pub fn new(params: SimParams, seed: u64) -> Simulation {
+        let mut sim = Simulation {
+            boids: Vec::new(),
+            back: Vec::new(),
+            predators: Vec::new(),
+            params,
+            grid: SpatialGrid::new(),
+            candidates: Candidates::default(),
+            leaders: Vec::new(),
+            densities: Vec::new(),
+            obstacles: Vec::new(),
+            attractors: Vec::new(),
+            species: vec![Species::default(); params.nb_species.max(1)],
+            path: default_path(params.width, params.height),
+            leader_target: None,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            metrics: Metrics::default(),
+        };
+        sim.load_boids();
+        sim.update_metrics();
+        sim
+    }
+
+    // Rebuild a simulation from a snapshot
+    pub fn from_snapshot(snapshot: Snapshot) -> Simulation {
+        let mut sim = Simulation {
+            boids: snapshot.boids,
+            back: Vec::new(),
+            predators: snapshot.predators,
+            params: snapshot.params,
+            grid: SpatialGrid::new(),
+            candidates: Candidates::default(),
+            leaders: Vec::new(),
+            densities: Vec::new(),
+            obstacles: snapshot.obstacles,
+            attractors: snapshot.attractors,
+            species: Vec::new(),
+            path: snapshot.path,
+            leader_target: None,
+            seed: snapshot.seed,
+            rng: snapshot.rng,
+            metrics: Metrics::default(),
+        };
+        // older snapshots have no species
+        let count = sim.params.nb_species.max(snapshot.species.len()).max(1);
+        sim.species = snapshot.species;
+        sim.species.resize(count, Species::default());
+        sim.params.nb_species = count;
+        if sim.path.is_empty() {
+            sim.path = default_path(sim.params.width, sim.params.height);
+        }
+        sim.update_metrics();
+        sim
+    }
+
+    // Copy of the complete state of the simulation
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            params: self.params,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            boids: self.boids.clone(),
+            predators: self.predators.clone(),
+            obstacles: self.obstacles.clone(),
+            attractors: self.attractors.clone(),
+            species: self.species.clone(),
+            path: self.path.clone(),
+        }
+    }
+
+    // Replace the state of the simulation with a snapshot
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        *self = Simulation::from_snapshot(snapshot);
+    }
+
+    // Read access to the boids of the simulation
+    pub fn boids(&self) -> &[Boid] {
+        &self.boids
+    }
+
+    // Read access to the predators of the simulation
+    pub fn predators(&self) -> &[Predator] {
+        &self.predators
+    }
+
+    // Obstacles of the world
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        self.obstacles.push(obstacle);
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles.clear();
+    }
+
+    // Attractors and repellers of the world
+    pub fn attractors(&self) -> &[Attractor] {
+        &self.attractors
+    }
+
+    pub fn add_attractor(&mut self, attractor: Attractor) {
+        self.attractors.push(attractor);
+    }
+
+    pub fn clear_attractors(&mut self) {
+        self.attractors.clear();
+    }
+
+    // Species of the boids (indexed by `Boid::species`)
+    pub fn species(&self) -> &[Species] {
+        &self.species
+    }
+
+    pub fn species_mut(&mut self) -> &mut [Species] {
+        &mut self.species
+    }
+
+    /**
+     * Replace the species (as many as `nb_species`, or as described if there are more)
+     * The boids are spread over them again, by id.
+     */
+    pub fn set_species(&mut self, mut species: Vec<Species>) {
+        let count = self.params.nb_species.max(species.len()).max(1);
+        species.resize(count, Species::default());
+        self.params.nb_species = count;
+        self.species = species;
+        for boid in &mut self.boids {
+            boid.species = boid.id as usize % count;
+        }
+    }
+
+    // Closed path of the leaders in path mode
+    pub fn path(&self) -> &[[f64; 2]] {
+        &self.path
+    }
+
+    // Replace the path of the leaders (the default loop if empty)
+    pub fn set_path(&mut self, path: Vec<[f64; 2]>) {
+        self.path = if path.is_empty() { default_path(self.params.width, self.params.height) } else { path };
+    }
+
+    // Point the leaders go to in manual mode (none : they keep their heading)
+    pub fn leader_target(&self) -> Option<[f64; 2]> {
+        self.leader_target
+    }
+
+    pub fn set_leader_target(&mut self, target: Option<[f64; 2]>) {
+        self.leader_target = target;
+    }
+
+    // Order parameters of the current frame
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+
+    // Seed the simulation was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Advance the simulation by `dt` seconds
+    pub fn step(&mut self, dt: f64) {
+        // the grid of the metrics is reused unless the parameters changed its dimensions
+        let (width, height, cell_size, wrap) = (self.params.width, self.params.height, self.cell_size(), self.params.boundary == BoundaryMode::Wrap);
+        if !self.grid.is_built_for(self.boids.len(), width, height, cell_size, wrap) {
+            self.grid.rebuild(&self.boids, width, height, cell_size, wrap);
+        }
+
+        // every boid of the write buffer reads the same (previous) frame,
+        // so the result does not depend on the update order
+        self.back.clear();
+        self.back.extend_from_slice(&self.boids);
+        self.leaders.clear();
+        self.leaders.extend(self.boids.iter().filter(|boid| boid.leader));
+        let surroundings = Surroundings {
+            obstacles: &self.obstacles,
+            predators: &self.predators,
+            attractors: &self.attractors,
+            species: &self.species,
+            leaders: &self.leaders,
+            path: &self.path,
+            leader_target: self.leader_target,
+        };
+        for boid in &mut self.back {
+            boid.update(dt, &self.boids, &self.grid, &self.params, &surroundings, &mut self.candidates, &mut self.rng);
+        }
+        std::mem::swap(&mut self.boids, &mut self.back);
+        self.rebuild_grid();
+
+        // the predators chase the new positions, then catch the boids within reach
+        if self.params.predator_target == PredatorTarget::Densest && !self.predators.is_empty() {
+            self.update_densities();
+        }
+        for predator in &mut self.predators {
+            predator.update(dt, &self.boids, &self.densities, &self.params);
+        }
+        if self.params.capture {
+            let (predators, params) = (&self.predators, &self.params);
+            let nb_boids = self.boids.len();
+            self.boids.retain(|boid| {
+                !predators.iter().any(|predator| {
+                    let offset = params.offset([predator.x, predator.y], [boid.x, boid.y]);
+                    offset[0].powi(2) + offset[1].powi(2) < params.capture_radius.powi(2)
+                })
+            });
+            if self.boids.len() != nb_boids {
+                self.rebuild_grid();
+            }
+        }
+        self.metrics = Metrics::compute(&self.boids, &self.grid, &self.params);
+    }
+
+    // Reset the simulation (Not the simulation parameters nor the RNG stream)
+    pub fn reset(&mut self) {
+        self.boids.clear();
+        self.predators.clear();
+        self.load_boids();
+        self.update_metrics();
+    }
+
+    // Largest of the rule radii of every species (size of the neighbor grid cells)
+    fn cell_size(&self) -> f64 {
+        self.species.iter().map(|species| species.apply(&self.params).max_radius()).fold(self.params.max_radius(), f64::max)
+    }
+
+    // Rebuild the grid on the current frame (used by the predators, the metrics and the next step)
+    fn rebuild_grid(&mut self) {
+        self.grid.rebuild(&self.boids, self.params.width, self.params.height, self.cell_size(), self.params.boundary == BoundaryMode::Wrap);
+    }
+
+    // Compute the order parameters of the current frame (the grid is rebuilt on it)
+    fn update_metrics(&mut self) {
+        self.rebuild_grid();
+        self.metrics = Metrics::compute(&self.boids, &self.grid, &self.params);
+    }
+
+    // Count the boids within the cohesion radius of each boid, whatever the neighbor mode and the perception cones
+    fn update_densities(&mut self) {
+        let (boids, params) = (&self.boids, &self.params);
+        self.densities.clear();
+        self.densities.extend(boids.iter().map(|boid| {
+            let mut count = 0;
+            self.grid.query(boid.x, boid.y, params.radius_cohesion, |i| {
+                let offset = params.offset([boid.x, boid.y], [boids[i].x, boids[i].y]);
+                if boids[i].id != boid.id && offset[0].powi(2) + offset[1].powi(2) < params.radius_cohesion.powi(2) {
+                    count += 1;
+                }
+            });
+            count
+        }));
+    }
+
+    // Load the boids and the predators in the simulation (random angles and positions)
+    fn load_boids(&mut self) {
+        let speed = VELCIRAPTOR_SPEED / self.params.frame_rate as f64;
+        for ct in 0..self.params.nb_boids {
+            let x = self.rng.gen_range(0.0..self.params.width);
+            let y = self.rng.gen_range(0.0..self.params.height);
+            let angle: f64 = self.rng.gen_range(0.0..2.0 * std::f64::consts::PI);
+
+            let mut boid = Boid::new(ct as i32, x, y, angle, speed);
+            boid.species = ct % self.species.len();
+            boid.traits = self.params.variation.sample(&mut self.rng);
+            boid.leader = ct < self.params.nb_leaders;
+            self.boids.push(boid);
+        }
+        for ct in 0..self.params.nb_predators {
+            let x = self.rng.gen_range(0.0..self.params.width);
+            let y = self.rng.gen_range(0.0..self.params.height);
+            let angle: f64 = self.rng.gen_range(0.0..2.0 * std::f64::consts::PI);
+
+            self.predators.push(Predator::new(ct as i32, x, y, angle, self.params.predator_speed));
+        }
+    }
+}
+
+// Loop of the leaders when none is given : an ellipse around the center of the world
+fn default_path(width: f64, height: f64) -> Vec<[f64; 2]> {
+    let nb_points = 12;
+    (0..nb_points)
+        .map(|i| {
+            let angle = 2.0 * std::f64::consts::PI * i as f64 / nb_points as f64;
+            [width / 2.0 + 0.35 * width * angle.cos(), height / 2.0 + 0.35 * height * angle.sin()]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variation::Distribution;
+
+    // Bits of the position and velocity of every boid
+    fn bits(sim: &Simulation) -> Vec<[u64; 4]> {
+        sim.boids().iter().map(|boid| [boid.x.to_bits(), boid.y.to_bits(), boid.velocity[0].to_bits(), boid.velocity[1].to_bits()]).collect()
+    }
+
+    #[test]
+    fn offset_takes_the_minimum_image() {
+        let wrap = SimParams { width: 100.0, height: 50.0, boundary: BoundaryMode::Wrap, ..SimParams::default() };
+        assert_eq!(wrap.offset([10.0, 10.0], [30.0, 20.0]), [20.0, 10.0]);
+        assert_eq!(wrap.offset([5.0, 5.0], [95.0, 45.0]), [-10.0, -10.0]);
+        assert_eq!(wrap.offset([95.0, 45.0], [5.0, 5.0]), [10.0, 10.0]);
+        // the components never exceed half of the world
+        for (from, to) in [([0.0, 0.0], [60.0, 30.0]), ([99.0, 1.0], [1.0, 49.0]), ([50.0, 25.0], [0.0, 0.0])] {
+            let offset = wrap.offset(from, to);
+            assert!(offset[0].abs() <= 50.0 && offset[1].abs() <= 25.0, "{:?} -> {:?} : {:?}", from, to, offset);
+        }
+
+        let bounce = SimParams { boundary: BoundaryMode::Bounce, ..wrap };
+        assert_eq!(bounce.offset([5.0, 5.0], [95.0, 45.0]), [90.0, 40.0]);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_run() {
+        let variation = Variation {
+            max_speed: Some(Distribution::Normal { mean: 1.0, std_dev: 0.2 }),
+            perception: Some(Distribution::Uniform { min: 0.8, max: 1.2 }),
+            ..Variation::default()
+        };
+        let params = SimParams { nb_boids: 200, weight_wander: 0.5, noise: 0.3, variation, ..SimParams::default() };
+        let run = |seed| {
+            let mut sim = Simulation::new(params, seed);
+            for _ in 0..200 {
+                sim.step(1.0 / 24.0);
+            }
+            bits(&sim)
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn changed_radii_rebuild_the_grid() {
+        let mut sim = Simulation::new(SimParams { nb_boids: 300, ..SimParams::default() }, 5);
+        for _ in 0..20 {
+            sim.step(1.0 / 24.0);
+        }
+        sim.params.radius_cohesion *= 3.0;
+        sim.params.width *= 0.5;
+        // a simulation rebuilt from scratch with the new parameters
+        let mut rebuilt = Simulation::from_snapshot(sim.snapshot());
+        for _ in 0..20 {
+            sim.step(1.0 / 24.0);
+            rebuilt.step(1.0 / 24.0);
+        }
+        assert_eq!(bits(&sim), bits(&rebuilt));
+    }
+
+    #[test]
+    fn densest_target_counts_the_boids_around() {
+        // nearest neighbors and a narrow cone : every boid sees a single cohesion neighbor
+        let params = SimParams {
+            nb_boids: 0,
+            nb_predators: 1,
+            predator_target: PredatorTarget::Densest,
+            neighbor_mode: NeighborMode::Topological,
+            k_cohesion: 1,
+            fov: 30.0,
+            boundary: BoundaryMode::Bounce,
+            ..SimParams::default()
+        };
+        let mut snapshot = Simulation::new(params, 1).snapshot();
+        // a pair close to the predator, a cluster of six further away
+        snapshot.boids = (0..8).map(|i| {
+            let x = if i < 2 { 300.0 + i as f64 * 5.0 } else { 1000.0 + i as f64 * 5.0 };
+            Boid::new(i, x, 400.0, 0.0, 1.0)
+        }).collect();
+        snapshot.predators = vec![Predator::new(0, 200.0, 400.0, 0.0, params.predator_speed)];
+        let mut sim = Simulation::from_snapshot(snapshot);
+        sim.step(1.0 / 24.0);
+        assert!(sim.predators()[0].target.is_some_and(|id| id >= 2), "target {:?}", sim.predators()[0].target);
+    }
+
+    #[test]
+    fn json_snapshot_continues_the_run_bit_for_bit() {
+        let params = SimParams { nb_boids: 300, ..SimParams::default() };
+        let mut original = Simulation::new(params, 42);
+        for _ in 0..50 {
+            original.step(1.0 / 24.0);
+        }
+        let text = serde_json::to_string(&original.snapshot()).unwrap();
+        let mut restored = Simulation::from_snapshot(serde_json::from_str(&text).unwrap());
+        assert_eq!(bits(&restored), bits(&original));
+
+        for _ in 0..200 {
+            original.step(1.0 / 24.0);
+            restored.step(1.0 / 24.0);
+        }
+        assert_eq!(bits(&restored), bits(&original));
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::SimParams;
+use crate::BOID_SIZE;
+
+// + Colors of the species without one (the first species uses the boid color) +
+const PALETTE: [[f32; 4]; 6] = [
+    [1.0, 0.6, 0.0, 1.0],
+    [0.0, 0.8, 1.0, 1.0],
+    [0.6, 1.0, 0.2, 1.0],
+    [1.0, 0.4, 0.7, 1.0],
+    [0.7, 0.5, 1.0, 1.0],
+    [1.0, 1.0, 0.4, 1.0],
+];
+// - Colors of the species -
+
+/**
+ * Kind of boid with its own look, speed, rule weights and radii
+ * Every field is optional, missing values keep the global parameters.
+ * In a configuration file : `[[species]]` tables, for example
+ * `name = "sparrows"`, `color = [1.0, 0.5, 0.0, 1.0]`, `weight_cohesion = 0.5`, `affinity = [1.0, 0.0, -1.0]`
+ */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Species {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 4]>, // Color of the boids (boid color or palette if not given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<f64>, // Length of the boids in pixels (`BOID_SIZE` if not given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_speed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_cohesion: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_separation: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_alignment: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius_cohesion: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius_separation: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius_alignment: Option<f64>,
+    /**
+     * Reaction to the boids of each species, by index (1 if not given) :
+     * 1 flocks with them, 0 only keeps them at the separation distance,
+     * -1 avoids them as far as the cohesion radius (in between : weaker)
+     */
+    pub affinity: Vec<f64>,
+    /**
+     * Alignment with the boids of each species, by index (`affinity` if not given) :
+     * 1 aligns with them, 0 or less ignores their heading
+     * (`affinity = [1.0, 0.0]` with `alignment_affinity = [1.0, 1.0]` : aligns with the
+     * second species without flocking towards it)
+     */
+    pub alignment_affinity: Vec<f64>,
+}
+
+impl Species {
+    // Global parameters with the values of the species
+    pub fn apply(&self, params: &SimParams) -> SimParams {
+        SimParams {
+            max_speed: self.max_speed.unwrap_or(params.max_speed),
+            weight_cohesion: self.weight_cohesion.unwrap_or(params.weight_cohesion),
+            weight_separation: self.weight_separation.unwrap_or(params.weight_separation),
+            weight_alignment: self.weight_alignment.unwrap_or(params.weight_alignment),
+            radius_cohesion: self.radius_cohesion.unwrap_or(params.radius_cohesion),
+            radius_separation: self.radius_separation.unwrap_or(params.radius_separation),
+            radius_alignment: self.radius_alignment.unwrap_or(params.radius_alignment),
+            ..*params
+        }
+    }
+
+    // Reaction to the boids of species `other`
+    pub fn affinity(&self, other: usize) -> f64 {
+        self.affinity.get(other).copied().unwrap_or(1.0)
+    }
+
+    // Alignment with the boids of species `other`
+    pub fn alignment_affinity(&self, other: usize) -> f64 {
+        self.alignment_affinity.get(other).copied().unwrap_or_else(|| self.affinity(other))
+    }
+
+    pub fn size(&self) -> f64 {
+        self.size.unwrap_or(BOID_SIZE)
+    }
+
+    // Color of the species at `index` (`boid` : color of the first species)
+    pub fn color(&self, index: usize, boid: [f32; 4]) -> [f32; 4] {
+        match self.color {
+            Some(color) => color,
+            None if index == 0 => boid,
+            None => PALETTE[(index - 1) % PALETTE.len()],
+        }
+    }
+
+    // Check that the species can be simulated
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [("size", self.size), ("max_speed", self.max_speed)];
+        for (name, value) in positive {
+            if value.is_some_and(|value| !(value.is_finite() && value > 0.0)) {
+                return Err(format!("`{}` of species `{}` must be strictly positive", name, self.name));
+            }
+        }
+        let non_negative = [("radius_cohesion", self.radius_cohesion), ("radius_separation", self.radius_separation), ("radius_alignment", self.radius_alignment)];
+        for (name, value) in non_negative {
+            if value.is_some_and(|value| !(value.is_finite() && value >= 0.0)) {
+                return Err(format!("`{}` of species `{}` must be positive or zero", name, self.name));
+            }
+        }
+        let finite = [("weight_cohesion", self.weight_cohesion), ("weight_separation", self.weight_separation), ("weight_alignment", self.weight_alignment)];
+        for (name, value) in finite {
+            if value.is_some_and(|value| !value.is_finite()) {
+                return Err(format!("`{}` of species `{}` must be a finite number", name, self.name));
+            }
+        }
+        if self.affinity.iter().chain(&self.alignment_affinity).any(|affinity| !affinity.is_finite()) {
+            return Err(format!("the affinities of species `{}` must be finite numbers", self.name));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alignment_affinity_defaults_to_the_affinity() {
+        let species = Species { affinity: vec![1.0, -1.0, 0.0], alignment_affinity: vec![0.0, 0.5], ..Species::default() };
+        assert_eq!([species.affinity(1), species.alignment_affinity(1)], [-1.0, 0.5]);
+        assert_eq!([species.affinity(2), species.alignment_affinity(2)], [0.0, 0.0]);
+        assert_eq!([species.affinity(5), species.alignment_affinity(5)], [1.0, 1.0]);
+        let invalid = Species { alignment_affinity: vec![f64::NAN], ..Species::default() };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::boid::Boid;
+use crate::simulation::SimParams;
+
+/**
+ * Trajectory files
+ * Compact binary recording of a run, frame by frame (little endian):
+ * header : magic `BOIDTRJ1`, u32 length + JSON of the `SimParams`
+ * frames : u32 number of boids, then for each boid
+ * i32 id, f32 x, f32 y, f32 angle, f32 vx, f32 vy
+ */
+const MAGIC: &[u8; 8] = b"BOIDTRJ1";
+
+// + Limits of the lengths read from a file (a corrupted length must not allocate gigabytes) +
+const MAX_HEADER_LEN: u32 = 1 << 20; // Length of the JSON parameters
+const MAX_FRAME_CAPACITY: usize = 1 << 16; // Boids allocated ahead of reading a frame
+// - Limits of the lengths -
+
+/**
+ * State of a boid in a recorded frame
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoidState {
+    pub id: i32,
+    pub x: f32,
+    pub y: f32,
+    pub angle: f32,
+    pub velocity: [f32; 2],
+}
+
+impl BoidState {
+    pub fn from_boid(boid: &Boid) -> BoidState {
+        BoidState {
+            id: boid.id,
+            x: boid.x as f32,
+            y: boid.y as f32,
+            angle: boid.angle as f32,
+            velocity: [boid.velocity[0] as f32, boid.velocity[1] as f32],
+        }
+    }
+
+    // Boid drawn at the recorded position (the rule vectors are not recorded)
+    pub fn to_boid(&self) -> Boid {
+        let mut boid = Boid::new(self.id, self.x as f64, self.y as f64, self.angle as f64, 0.0);
+        boid.velocity = [self.velocity[0] as f64, self.velocity[1] as f64];
+        boid
+    }
+}
+
+/**
+ * Streams the frames of a run to a trajectory file
+ */
+pub struct TrajectoryWriter {
+    out: BufWriter<File>,
+}
+
+impl TrajectoryWriter {
+    pub fn create(path: &Path, params: &SimParams) -> io::Result<TrajectoryWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let header = serde_json::to_vec(params).map_err(io::Error::other)?;
+        out.write_all(MAGIC)?;
+        out.write_all(&(header.len() as u32).to_le_bytes())?;
+        out.write_all(&header)?;
+        Ok(TrajectoryWriter { out })
+    }
+
+    // Append the current state of the boids as a new frame
+    pub fn write_frame(&mut self, boids: &[Boid]) -> io::Result<()> {
+        self.out.write_all(&(boids.len() as u32).to_le_bytes())?;
+        for boid in boids {
+            let state = BoidState::from_boid(boid);
+            self.out.write_all(&state.id.to_le_bytes())?;
+            for value in [state.x, state.y, state.angle, state.velocity[0], state.velocity[1]] {
+                self.out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/**
+ * Trajectory file loaded in memory (for replay and scrubbing)
+ */
+pub struct Trajectory {
+    pub params: SimParams,          // Parameters of the recorded run
+    pub frames: Vec<Vec<BoidState>>, // Boids of each frame
+}
+
+impl Trajectory {
+    pub fn load(path: &Path) -> io::Result<Trajectory> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a trajectory file"));
+        }
+        let header_len = read_u32(&mut input)?;
+        if header_len > MAX_HEADER_LEN {
+            return Err(invalid_data(format!("header of {} bytes (at most {})", header_len, MAX_HEADER_LEN)));
+        }
+        let mut header = Vec::new();
+        input.by_ref().take(header_len as u64).read_to_end(&mut header)?;
+        if header.len() != header_len as usize {
+            return Err(invalid_data("truncated header"));
+        }
+        let params: SimParams = serde_json::from_slice(&header).map_err(invalid_data)?;
+
+        let mut frames = Vec::new();
+        // a missing frame count is the end of the file, a truncated frame is an error
+        while let Some(nb_boids) = read_frame_len(&mut input)? {
+            // the count is only trusted as far as the boids are actually there
+            let mut frame = Vec::with_capacity((nb_boids as usize).min(MAX_FRAME_CAPACITY));
+            for _ in 0..nb_boids {
+                let id = read_u32(&mut input).map_err(truncated_frame)? as i32;
+                let mut values = [0f32; 5];
+                for value in &mut values {
+                    *value = f32::from_bits(read_u32(&mut input).map_err(truncated_frame)?);
+                }
+                frame.push(BoidState {
+                    id,
+                    x: values[0],
+                    y: values[1],
+                    angle: values[2],
+                    velocity: [values[3], values[4]],
+                });
+            }
+            frames.push(frame);
+        }
+        Ok(Trajectory { params, frames })
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+// A frame cut before its announced number of boids
+fn truncated_frame(error: io::Error) -> io::Error {
+    if error.kind() == io::ErrorKind::UnexpectedEof {
+        invalid_data("truncated frame")
+    } else {
+        error
+    }
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_frame_len<R: Read>(input: &mut R) -> io::Result<Option<u32>> {
+    let mut bytes = [0u8; 4];
+    match input.read_exact(&mut bytes) {
+        Ok(()) => Ok(Some(u32::from_le_bytes(bytes))),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn recorded_frames_load_back() {
+        let path = std::env::temp_dir().join(format!("boids-trajectory-{}.bin", std::process::id()));
+        let params = SimParams { nb_boids: 20, ..SimParams::default() };
+        let mut sim = Simulation::new(params, 3);
+        let mut recorded = Vec::new();
+        let mut writer = TrajectoryWriter::create(&path, &sim.params).unwrap();
+        for _ in 0..5 {
+            writer.write_frame(sim.boids()).unwrap();
+            recorded.push(sim.boids().iter().map(BoidState::from_boid).collect::<Vec<_>>());
+            sim.step(1.0 / 24.0);
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        let trajectory = Trajectory::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let trajectory = trajectory.unwrap();
+        assert_eq!(trajectory.params, params);
+        assert_eq!(trajectory.frames, recorded);
+    }
+
+    #[test]
+    fn corrupted_lengths_are_invalid_data() {
+        let path = std::env::temp_dir().join(format!("boids-corrupted-{}.bin", std::process::id()));
+        let header = serde_json::to_vec(&SimParams::default()).unwrap();
+        let load = |bytes: &[u8]| {
+            std::fs::write(&path, bytes).unwrap();
+            let error = Trajectory::load(&path).err().map(|e| e.kind());
+            std::fs::remove_file(&path).unwrap();
+            error
+        };
+
+        // huge header length
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(u32::MAX.to_le_bytes());
+        assert_eq!(load(&bytes), Some(io::ErrorKind::InvalidData));
+
+        // header shorter than announced
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((header.len() as u32 + 10).to_le_bytes());
+        bytes.extend(&header);
+        assert_eq!(load(&bytes), Some(io::ErrorKind::InvalidData));
+
+        // frame announcing more boids than the file holds
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((header.len() as u32).to_le_bytes());
+        bytes.extend(&header);
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend([0u8; 24]);
+        assert_eq!(load(&bytes), Some(io::ErrorKind::InvalidData));
+    }
+}
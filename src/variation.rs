@@ -0,0 +1,139 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::SimParams;
+
+/**
+ * Random distribution of a trait multiplier
+ * In a configuration file :
+ * `{ distribution = "uniform", min = a, max = b }`
+ * `{ distribution = "normal", mean = m, std_dev = s }`
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum Distribution {
+    Uniform { min: f64, max: f64 },
+    Normal { mean: f64, std_dev: f64 },
+}
+
+impl Distribution {
+    // Draw a value (negative values are clamped to 0)
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        let value = match *self {
+            Distribution::Uniform { min, max } => min + (max - min) * rng.gen::<f64>(),
+            Distribution::Normal { mean, std_dev } => {
+                // Box-Muller transform (1 - u keeps the logarithm finite)
+                let u1 = 1.0 - rng.gen::<f64>();
+                let u2: f64 = rng.gen();
+                mean + std_dev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+            },
+        };
+        value.max(0.0)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Distribution::Uniform { min, max } => {
+                if !(min.is_finite() && max.is_finite() && min <= max) {
+                    return Err(format!("a uniform distribution needs finite bounds with min <= max (got {} and {})", min, max));
+                }
+            },
+            Distribution::Normal { mean, std_dev } => {
+                if !(mean.is_finite() && std_dev.is_finite() && std_dev >= 0.0) {
+                    return Err(format!("a normal distribution needs a finite mean and a positive standard deviation (got {} and {})", mean, std_dev));
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Distributions the traits of each boid are drawn from at spawn time
+ * (multipliers of the parameters of its species, 1 if not given)
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Variation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_speed: Option<Distribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub perception: Option<Distribution>, // Radii of the three rules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_cohesion: Option<Distribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_separation: Option<Distribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_alignment: Option<Distribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<Distribution>,
+}
+
+impl Variation {
+    /**
+     * Draw the traits of a boid
+     * Only the given distributions use the RNG, so a run without variation
+     * keeps the same random stream.
+     */
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Traits {
+        let mut draw = |distribution: Option<Distribution>| distribution.map_or(1.0, |distribution| distribution.sample(rng));
+        Traits {
+            max_speed: draw(self.max_speed),
+            perception: draw(self.perception),
+            weight_cohesion: draw(self.weight_cohesion),
+            weight_separation: draw(self.weight_separation),
+            weight_alignment: draw(self.weight_alignment),
+            size: draw(self.size),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let distributions = [self.max_speed, self.perception, self.weight_cohesion, self.weight_separation, self.weight_alignment, self.size];
+        for distribution in distributions.iter().flatten() {
+            distribution.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Individual multipliers of a boid (1 : like the rest of its species)
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Traits {
+    pub max_speed: f64,
+    pub perception: f64,
+    pub weight_cohesion: f64,
+    pub weight_separation: f64,
+    pub weight_alignment: f64,
+    pub size: f64,
+}
+
+impl Default for Traits {
+    fn default() -> Traits {
+        Traits {
+            max_speed: 1.0,
+            perception: 1.0,
+            weight_cohesion: 1.0,
+            weight_separation: 1.0,
+            weight_alignment: 1.0,
+            size: 1.0,
+        }
+    }
+}
+
+impl Traits {
+    // Parameters of the species scaled by the traits
+    pub fn apply(&self, params: &SimParams) -> SimParams {
+        SimParams {
+            max_speed: params.max_speed * self.max_speed,
+            weight_cohesion: params.weight_cohesion * self.weight_cohesion,
+            weight_separation: params.weight_separation * self.weight_separation,
+            weight_alignment: params.weight_alignment * self.weight_alignment,
+            radius_cohesion: params.radius_cohesion * self.perception,
+            radius_separation: params.radius_separation * self.perception,
+            radius_alignment: params.radius_alignment * self.perception,
+            ..*params
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use boids::{SimParams, Simulation};
+
+// The simulation runs and exposes its boids without any window
+#[test]
+fn simulation_steps_without_a_window() {
+    let params = SimParams { nb_boids: 50, ..SimParams::default() };
+    let mut sim = Simulation::new(params, 1);
+    let start: Vec<[f64; 2]> = sim.boids().iter().map(|boid| [boid.x, boid.y]).collect();
+    for _ in 0..10 {
+        sim.step(1.0 / params.frame_rate as f64);
+    }
+    assert_eq!(sim.boids().len(), 50);
+    assert!(sim.boids().iter().zip(&start).any(|(boid, start)| [boid.x, boid.y] != *start));
+    for boid in sim.boids() {
+        assert!(boid.x.is_finite() && boid.y.is_finite());
+        assert!((0.0..=params.width).contains(&boid.x) && (0.0..=params.height).contains(&boid.y));
+    }
+}